@@ -1,13 +1,15 @@
 use anyhow::{anyhow, bail, Context, Result};
+use app_component::AppComponentAddCommand;
 use clap::Args;
 use convert_case::{Case, Casing};
-use http::HttpAddCommand;
+use http::{HttpAddCommand, HttpOptions};
 use local::LocalAddCommand;
 use registry::RegistryAddCommand;
 use semver::{Version, VersionReq};
+use spin_loader::cache::Cache;
 use spin_manifest::{
     manifest_from_file,
-    schema::v2::{AppManifest, ComponentDependency},
+    schema::v2::{AppManifest, ComponentDependencies, ComponentDependency},
 };
 use spin_serde::{DependencyName, DependencyPackageName, KebabId};
 use std::{
@@ -16,18 +18,38 @@ use std::{
 };
 use tokio::fs;
 use url::Url;
+use wasi_preview1_component_adapter_provider::WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER;
 use wasm_pkg_client::{PackageRef, Registry};
-use wit_parser::{PackageId, Resolve};
+use wit_parser::{InterfaceId, PackageId, Resolve, WorldId, WorldItem, WorldKey};
 
 use crate::common::{
+    cache::{absolute_manifest_path, CacheReference, CacheVerification, ReferenceIndex},
+    capabilities::imported_capabilities,
     constants::SPIN_WIT_DIRECTORY,
-    interact::{select_multiple_prompt, select_prompt},
-    manifest::{edit_component_deps_in_manifest, get_component_ids},
+    digest::ContentDigest,
+    host::unsatisfied_imports,
+    interact::{confirm_prompt, input_prompt, select_multiple_prompt, select_prompt},
+    lockfile::{AppLockfile, LockedDependency},
+    manifest::{
+        dependency_export, describe_dependency, edit_component_deps_in_manifest, get_component_ids,
+        same_source, with_export,
+    },
+    network::NetworkOptions,
     paths::fs_safe_segment,
-    wit::{get_exported_interfaces, parse_component_bytes, resolve_to_wit},
+    wit::{
+        add_plain_import_world, async_functions, componentize, enabled_features,
+        export_package_interfaces, find_export, get_exported_interfaces, get_plain_exports,
+        interface_closure, is_core_module, parse_component_bytes, parse_wit_path, resolve_to_wit,
+        write_deps_tree, write_package, FeatureGates, SourceKind,
+    },
+    wit_deps::DepsTree,
 };
-use js_component_bindgen::{generate_types, TranspileOpts};
+use crate::language::rust::{
+    sdk_wit_bindgen_version, MacroSupport, ASYNC_IMPORTS_SINCE, FEATURES_SINCE,
+};
+use js_component_bindgen::{generate_types, AsyncMode, TranspileOpts};
 
+mod app_component;
 mod http;
 mod local;
 mod registry;
@@ -35,68 +57,217 @@ mod registry;
 #[derive(Args, Debug)]
 pub struct AddCommand {
     /// Source to the component. Can be one of a local path, a HTTP URL or a registry reference.
-    pub source: String,
-    /// Sha256 digest that will be used to verify HTTP downloads. Required for HTTP sources, ignored otherwise.
+    #[clap(required_unless_present = "from_component")]
+    pub source: Option<String>,
+    /// Add another component of this application as the dependency. Builds the component if
+    /// its Wasm source does not exist yet.
+    #[clap(long, value_name = "COMPONENT_ID", conflicts_with_all = ["source", "digest", "registry"])]
+    pub from_component: Option<String>,
+    /// Digest that will be used to verify HTTP downloads. Accepts SHA-256 or SHA-512 as bare hex,
+    /// `<algorithm>:<hex>` or Subresource Integrity `<algorithm>-<base64>`. Required for HTTP
+    /// sources unless --trust-on-first-use is passed, ignored otherwise.
+    #[clap(short, long)]
+    pub digest: Option<ContentDigest>,
+    /// Accept prompts for confirmation without asking.
     #[clap(short, long)]
-    pub digest: Option<String>,
+    pub yes: bool,
+    #[clap(flatten)]
+    pub http: HttpOptions,
+    #[clap(flatten)]
+    pub network: NetworkOptions,
+    /// Resolve sources only from the cache and never access the network. Registry versions are
+    /// resolved from the releases the app's spin-deps.lock locks, then from releases spin deps
+    /// fetched earlier.
+    #[clap(long)]
+    pub offline: bool,
+    /// How to check cached components before using them. Corrupt entries are fetched again.
+    #[clap(long, value_enum, default_value_t)]
+    pub cache_verification: CacheVerification,
     /// Registry to override the default with. Ignored in the cases of local or HTTP sources.
     #[clap(short, long)]
     pub registry: Option<Registry>,
     /// The Spin component to add the dependency to. If omitted, it is prompted for.
     #[clap(long = "to")]
     pub add_to_component: Option<String>,
+    /// The world of the source to take exports from, for sources with several worlds. If
+    /// omitted, it is prompted for.
+    #[clap(long)]
+    pub world: Option<String>,
+    /// Do not copy doc comments from the source into the generated WIT and bindings.
+    #[clap(long)]
+    pub no_docs: bool,
+    /// Enable WIT items gated behind these `@unstable` features. Can be repeated or
+    /// comma-separated.
+    #[clap(long, value_delimiter = ',')]
+    pub features: Vec<String>,
+    /// Enable every WIT item gated behind an `@unstable` feature.
+    #[clap(long, conflicts_with = "features")]
+    pub all_features: bool,
+    /// The name to record the dependency under in the manifest, instead of the name the source
+    /// exports it under. Must be kebab-case, and only one interface or function may be selected.
+    /// The generated bindings import the dependency under this name.
+    #[clap(long, value_parser = parse_kebab_id)]
+    pub name: Option<KebabId>,
     /// The path to the manifest. This can be a file or directory. The default is 'spin.toml'.
     #[clap(short = 'f')]
     pub manifest_path: Option<PathBuf>,
+    /// Replace existing dependencies that clash with the new ones without prompting.
+    #[clap(long)]
+    pub force: bool,
+    /// Turn a local core Wasm module into a component without prompting. The module must embed
+    /// its WIT in a `component-type` custom section.
+    #[clap(long)]
+    pub componentize: bool,
+    /// The WASI preview1 adapter used to componentize core modules. Defaults to the bundled
+    /// reactor adapter.
+    #[clap(long, value_name = "PATH")]
+    pub adapter: Option<PathBuf>,
+}
+
+/// What was selected from one of the packages the source exports from.
+struct PackageSelection {
+    /// Whether the package as a whole was selected, rather than one interface.
+    whole_package: bool,
+    /// The qualified names of the selected interfaces.
+    interfaces: Vec<String>,
+}
+
+impl PackageSelection {
+    /// The name the selection is recorded under in the manifest: the package
+    /// itself for a whole-package selection, otherwise the interface.
+    fn dependency_name(&self, package: &wit_parser::PackageName) -> String {
+        if self.whole_package {
+            package.to_string()
+        } else {
+            self.interfaces[0].clone()
+        }
+    }
 }
 
 enum ComponentSource {
     Local(LocalAddCommand),
+    AppComponent(AppComponentAddCommand),
     Http(HttpAddCommand),
     Registry(RegistryAddCommand),
 }
 
 impl ComponentSource {
-    pub fn infer_source(
-        source: &String,
-        digest: &Option<String>,
-        registry: &Option<Registry>,
-    ) -> Result<Self> {
-        let path = PathBuf::from(&source);
-        if path.exists() {
-            return Ok(Self::Local(LocalAddCommand { path }));
-        }
-
-        if let Ok(url) = Url::parse(source) {
-            if url.scheme().starts_with("http") {
-                return digest.clone().map_or_else(
-                    || bail!("Digest needs to be specified for HTTP sources."),
-                    |d| Ok(Self::Http(HttpAddCommand { url, digest: d })),
-                );
-            }
+    /// A description of the source for messages.
+    fn describe(&self) -> String {
+        match self {
+            ComponentSource::Local(cmd) => cmd.path.display().to_string(),
+            ComponentSource::AppComponent(cmd) => format!("component '{}'", cmd.component_id),
+            ComponentSource::Http(cmd) => cmd.url.to_string(),
+            ComponentSource::Registry(cmd) => format!("{}@{}", cmd.package, cmd.version),
         }
+    }
 
-        if let Ok((name, version)) = package_name_ver(source) {
-            if version.is_none() {
-                bail!("Version needs to specified for registry sources.")
-            }
-            return Ok(Self::Registry(RegistryAddCommand {
-                package: name,
-                version: version.unwrap(),
-                registry: registry.clone(),
-            }));
+    /// A kebab-case name for the source, used to name bindings that do not
+    /// belong to a WIT package.
+    fn binding_name(&self) -> String {
+        let name = match self {
+            ComponentSource::Local(cmd) => cmd
+                .path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ComponentSource::AppComponent(cmd) => cmd.component_id.clone(),
+            ComponentSource::Http(cmd) => cmd
+                .url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .and_then(|segment| segment.split('.').next())
+                .filter(|stem| !stem.is_empty())
+                .or(cmd.url.host_str())
+                .unwrap_or_default()
+                .to_owned(),
+            ComponentSource::Registry(cmd) => cmd.package.name().to_string(),
+        };
+        let name = name
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>()
+            .join("-");
+        if name.is_empty() {
+            "component".to_owned()
+        } else {
+            name
         }
-
-        bail!("Could not infer component source");
     }
 
-    pub async fn get_component(&self) -> Result<Vec<u8>> {
-        match &self {
+    pub async fn get_component(&mut self) -> Result<Vec<u8>> {
+        match self {
             ComponentSource::Local(cmd) => cmd.get_component().await,
+            ComponentSource::AppComponent(cmd) => cmd.get_component().await,
             ComponentSource::Http(cmd) => cmd.get_component().await,
             ComponentSource::Registry(cmd) => cmd.get_component().await,
         }
     }
+
+    /// The dependency to record for the source.
+    fn dependency(&self) -> Result<ComponentDependency> {
+        Ok(match self {
+            ComponentSource::Local(src) => ComponentDependency::Local {
+                path: src.path.clone(),
+                export: None,
+            },
+            ComponentSource::AppComponent(src) => ComponentDependency::Local {
+                path: src.path.clone(),
+                export: None,
+            },
+            ComponentSource::Http(src) => ComponentDependency::HTTP {
+                url: src.url.to_string(),
+                digest: src
+                    .digest
+                    .as_ref()
+                    .context("HTTP source digest has not been determined")?
+                    .to_string(),
+                export: None,
+            },
+            ComponentSource::Registry(src) => ComponentDependency::Package {
+                version: src.version.to_string(),
+                registry: src.registry.as_ref().map(|registry| registry.to_string()),
+                package: Some(src.package.to_string()),
+                export: None,
+            },
+        })
+    }
+
+    /// The digest the fetched component is cached under, and a description of
+    /// where it came from. Only known for HTTP and registry sources.
+    fn cached_digest(&self) -> Option<(String, String)> {
+        match self {
+            ComponentSource::Http(cmd) => {
+                Some((cmd.digest.as_ref()?.to_string(), cmd.url.to_string()))
+            }
+            ComponentSource::Registry(cmd) => Some((cmd.digest.clone()?, self.describe())),
+            ComponentSource::Local(_) | ComponentSource::AppComponent(_) => None,
+        }
+    }
+
+    /// What a dependency on the fetched component is locked to. Only known
+    /// for HTTP and registry sources.
+    fn locked(&self, component: &str, dependency: &str) -> Option<LockedDependency> {
+        let (source, version, digest) = match self {
+            ComponentSource::Http(cmd) => {
+                (cmd.url.to_string(), None, cmd.digest.as_ref()?.to_string())
+            }
+            ComponentSource::Registry(cmd) => (
+                cmd.package.to_string(),
+                cmd.release.clone(),
+                cmd.digest.clone()?,
+            ),
+            ComponentSource::Local(_) | ComponentSource::AppComponent(_) => return None,
+        };
+        Some(LockedDependency {
+            component: component.to_owned(),
+            dependency: dependency.to_owned(),
+            source,
+            version,
+            digest,
+        })
+    }
 }
 
 impl AddCommand {
@@ -111,21 +282,54 @@ impl AddCommand {
         }
         let manifest_file = manifest_file.canonicalize()?;
 
-        let mut manifest = manifest_from_file(&manifest_file)?;
+        let manifest = manifest_from_file(&manifest_file)?;
+
+        let root_dir = manifest_file
+            .parent()
+            .ok_or_else(|| anyhow!("Manifest cannot be the root directory"))?;
 
-        let source = ComponentSource::infer_source(&self.source, &self.digest, &self.registry)?;
+        let mut source = if let Some(component_id) = &self.from_component {
+            ComponentSource::AppComponent(AppComponentAddCommand::new(
+                &manifest,
+                root_dir,
+                component_id,
+            )?)
+        } else {
+            let source = self
+                .source
+                .as_ref()
+                .context("Either a source or --from-component must be specified")?;
+            self.infer_source(source, &manifest_file)?
+        };
 
-        let component = source.get_component().await?;
+        let (mut resolve, package, kind) = self.decode_source(&mut source).await?;
+        let world_id = self.select_world(&mut resolve, package, kind)?;
+        let main = resolve.worlds[world_id]
+            .package
+            .context("Selected world does not belong to a package")?;
 
-        let (mut resolve, main) = parse_component_bytes(component)?;
+        let plain_exports = get_plain_exports(&resolve, world_id);
+        if get_exported_interfaces(&resolve, world_id).is_empty() && plain_exports.is_empty() {
+            bail!("No exported interfaces or functions found in the component")
+        }
 
-        let selected_interface_map = self.select_interfaces(&mut resolve, main)?;
-        if selected_interface_map.is_empty() {
-            println!("No interfaces selected");
+        let selected_interface_map = self.select_interfaces(&resolve, world_id)?;
+        let selected_plain_exports = self.select_plain_exports(&plain_exports)?;
+        if selected_interface_map.is_empty() && selected_plain_exports.is_empty() {
+            println!("No interfaces or functions selected");
             return Ok(());
         }
+        if self.name.is_some() && selected_interface_map.len() + selected_plain_exports.len() != 1 {
+            bail!("--name can only be used when a single interface or function is selected");
+        }
+        if self.name.is_some() && selected_interface_map.values().any(|s| s.whole_package) {
+            bail!("--name cannot be used for a whole package; select a single interface instead");
+        }
 
         let selected_component = self.target_component(&manifest)?;
+        if self.from_component.as_ref() == Some(&selected_component) {
+            bail!("A component cannot be added as a dependency of itself");
+        }
 
         // {
         //     let package = resolve.packages.get_mut(main).unwrap();
@@ -146,80 +350,252 @@ impl AddCommand {
             .get(&target_component_id)
             .ok_or_else(|| anyhow!("component does not exist"))?;
 
-        let root_dir = manifest_file
-            .parent()
-            .ok_or_else(|| anyhow!("Manifest cannot be the root directory"))?;
+        let unsatisfied = unsatisfied_imports(&resolve, world_id, &target_component.dependencies);
+        if !unsatisfied.is_empty() {
+            println!("The dependency imports interfaces that neither Spin nor the other dependencies of '{selected_component}' provide:");
+            for import in &unsatisfied {
+                println!("  {import}");
+            }
+            if !self.yes && !confirm_prompt("Add the dependency anyway?", false)? {
+                bail!("The dependency's imports cannot be satisfied");
+            }
+        }
 
-        // gen bindings
-        for package in selected_interface_map.keys() {
-            // if id != main {
-            //     continue;  // TODO: yes, this is a silly way to just do main
-            // }
-            let id = resolve
-                .packages
-                .iter()
-                .find(|(_, p)| &p.name == package)
-                .unwrap()
-                .0;
+        self.check_capabilities(&resolve, world_id, &selected_component, target_component)?;
+
+        // Clashes with existing dependencies are resolved before anything is
+        // written, so that skipped selections leave nothing behind and
+        // bindings are generated under the names the dependencies end up with.
+        let component_dependency = source.dependency()?;
+        let mut dependencies = target_component.dependencies.clone();
+        let mut removed = Vec::new();
+        let mut dependency_names = Vec::new();
+        let mut package_selections = Vec::new();
+        let mut plain_imports = Vec::new();
+
+        for (package, selection) in &selected_interface_map {
+            let export = selection.dependency_name(package);
+            let name = DependencyPackageName::try_from(export.clone())?;
+            let dependency = match kind {
+                SourceKind::Component => component_dependency.clone(),
+                SourceKind::WitPackage => placeholder_dependency(&name),
+            };
+            let (name, dependency) = match &self.name {
+                Some(plain) => (
+                    DependencyName::Plain(plain.clone()),
+                    with_export(dependency, &package.to_string(), export.clone()),
+                ),
+                None => (DependencyName::Package(name), dependency),
+            };
+            match self.insert_dependency(
+                &mut dependencies,
+                &selected_component,
+                name,
+                &package.to_string(),
+                dependency,
+                &mut removed,
+            )? {
+                Insertion::Added(added @ DependencyName::Package(_)) => {
+                    package_selections.push((package, selection, added.to_string()));
+                    dependency_names.push(added);
+                }
+                // Under a plain name, from --name or a rename, the interface
+                // is bound through an import of that name.
+                Insertion::Added(added) => {
+                    plain_imports.push((added.to_string(), export));
+                    dependency_names.push(added);
+                }
+                Insertion::Covered | Insertion::Skipped => {}
+            }
+        }
 
-            let fs_name = fs_safe_segment(package.name.to_string());
+        // Plain exports are recorded under their own name, which is already
+        // kebab-case, unless --name gives another.
+        for export in &selected_plain_exports {
+            let (name, dependency) = match &self.name {
+                Some(plain) => (
+                    plain.clone(),
+                    with_export(component_dependency.clone(), "", export.clone()),
+                ),
+                None => (parse_kebab_id(export)?, component_dependency.clone()),
+            };
+            match self.insert_dependency(
+                &mut dependencies,
+                &selected_component,
+                DependencyName::Plain(name),
+                "",
+                dependency,
+                &mut removed,
+            )? {
+                Insertion::Added(added) => {
+                    plain_imports.push((added.to_string(), export.clone()));
+                    dependency_names.push(added);
+                }
+                Insertion::Covered | Insertion::Skipped => {}
+            }
+        }
 
-            let dep_dir = PathBuf::from(SPIN_WIT_DIRECTORY)
-                .join("deps")
-                .join(&fs_name);
-            std::fs::create_dir_all(&dep_dir)?;
+        if dependency_names.is_empty() {
+            println!("No dependencies were added to component '{selected_component}'");
+            return Ok(());
+        }
 
-            let output_wit_file = format!(
-                "{ns}-{name}.wit",
-                ns = package.namespace,
-                name = package.name
+        // Every package but the component's own goes into the shared deps
+        // tree, one directory per package.
+        // What the dependencies being added again or replaced used before
+        // does not count against them.
+        let deps_dir = PathBuf::from(SPIN_WIT_DIRECTORY).join("deps");
+        let description = source.describe();
+        let replacing = removed
+            .iter()
+            .chain(&dependency_names)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let mut deps_tree = DepsTree::load(&deps_dir)?;
+        deps_tree.check_conflicts(
+            &resolve,
+            &[main],
+            &selected_component,
+            &replacing,
+            &description,
+        )?;
+        let wit_paths = write_deps_tree(&resolve, &deps_dir, &[main], !self.no_docs)?;
+
+        // The bindings of replaced dependencies go before new ones are
+        // generated, since a new dependency may be bound in the same place.
+        for name in &removed {
+            for path in deps_tree.remove(&selected_component, &name.to_string()) {
+                remove_bindings(root_dir, &path).await?;
+            }
+        }
+        for name in &dependency_names {
+            deps_tree.record(
+                &resolve,
+                &[main],
+                &selected_component,
+                &name.to_string(),
+                &description,
             );
-            let output_wit_path = dep_dir.join(output_wit_file);
+        }
 
-            let output_wit_text =
-                resolve_to_wit(&resolve, id).context("failed to resolve to wit")?;
+        // gen bindings
+        for (package, selection, dependency_name) in package_selections {
+            let interfaces =
+                module_interfaces(&resolve, &dependencies, package, &selection.interfaces);
+            let features = enabled_features(&resolve);
 
-            fs::write(&output_wit_path, output_wit_text)
-                .await
-                .context("failed to write wit")?;
+            let target = BindOMatic {
+                // manifest: &manifest,
+                root_dir,
+                target_component,
+                package_name: package,
+                resolve: &resolve,
+                interfaces: &interfaces,
+                worlds: &[],
+                plain_imports: &[],
+                features: &features,
+                docs: !self.no_docs,
+                wit_paths: &wit_paths,
+            };
+            let bindings = try_generate_bindings(&target).await?;
+            deps_tree.record_bindings(&selected_component, &dependency_name, &bindings);
+        }
+
+        // Functions and unqualified interfaces are not part of any package,
+        // and nor is anything imported under a name of its own, so they are
+        // bound through a world of the component's package that imports them.
+        if !plain_imports.is_empty() {
+            // The module is rewritten as a whole, so it keeps binding what
+            // was added from this source under plain names before.
+            for (name, dependency) in &dependencies.inner {
+                let DependencyName::Plain(name) = name else {
+                    continue;
+                };
+                let name = name.to_string();
+                if !same_source(dependency, &component_dependency)
+                    || plain_imports.iter().any(|(import, _)| *import == name)
+                {
+                    continue;
+                }
+                let export = dependency_export(dependency).unwrap_or(&name).to_owned();
+                if find_export(&resolve, world_id, &export).is_some() {
+                    plain_imports.push((name, export));
+                }
+            }
 
-            // I _think_ we have to generate bindings for *all* the interfaces
-            // because of the possibility of dependencies
-            let interfaces = resolve
-                .packages
+            let package_name = wit_parser::PackageName {
+                namespace: "exports".to_owned(),
+                name: source.binding_name(),
+                version: None,
+            };
+            let world_name = format!("{}-{}", package_name.namespace, package_name.name);
+            let plain_world_id =
+                add_plain_import_world(&mut resolve, world_id, &plain_imports, &world_name)?;
+
+            // The component's package is specific to this source, so it
+            // gets a directory of its own rather than one named for it.
+            let dep_dir = deps_dir.join(fs_safe_segment(world_name.clone()));
+            write_package(&resolve, main, &dep_dir, !self.no_docs)?;
+            let wit_paths = wit_paths
                 .iter()
-                .flat_map(|(_, p)| {
-                    p.interfaces
-                        .keys()
-                        .map(|itf_name| qualified_itf_name(&p.name, itf_name))
+                .cloned()
+                .chain(std::iter::once(dep_dir))
+                .collect::<Vec<_>>();
+
+            // Interfaces imported under a plain name have no qualified name
+            // of their own, but the interfaces they use still need binding.
+            let roots = resolve.worlds[plain_world_id]
+                .imports
+                .values()
+                .filter_map(|item| match item {
+                    WorldItem::Interface { id, .. } => Some(*id),
+                    _ => None,
                 })
                 .collect::<Vec<_>>();
+            let interfaces = qualified_closure(&resolve, roots);
+            let features = enabled_features(&resolve);
+            let worlds = vec![format!("{}/{world_name}", resolve.packages[main].name)];
+            let import_names = plain_imports
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
 
             let target = BindOMatic {
-                // manifest: &manifest,
                 root_dir,
                 target_component,
-                package_name: package,
+                package_name: &package_name,
                 resolve: &resolve,
                 interfaces: &interfaces,
-                rel_wit_path: &output_wit_path,
+                worlds: &worlds,
+                plain_imports: &import_names,
+                features: &features,
+                docs: !self.no_docs,
+                wit_paths: &wit_paths,
             };
-            try_generate_bindings(&target).await?;
+            let bindings = try_generate_bindings(&target).await?;
+            for name in &import_names {
+                deps_tree.record_bindings(&selected_component, name, &bindings);
+            }
         }
+        deps_tree.save()?;
 
-        let selected_interfaces = selected_interface_map
-            .values()
-            .flatten()
-            .cloned()
-            .collect::<Vec<_>>();
         self.update_manifest(
-            source,
+            &source,
+            kind,
             &manifest_file,
-            &mut manifest,
             &selected_component,
-            &selected_interfaces,
+            &dependencies,
+            &dependency_names,
         )
         .await?;
+        update_lockfile(
+            &source,
+            kind,
+            &manifest_file,
+            &selected_component,
+            &dependency_names,
+            &removed,
+        )?;
 
         // let target_component_id = KebabId::try_from(selected_component.clone()).map_err(|e| anyhow!("{e}"))?;
         // let target_component = manifest.components.get(&target_component_id).ok_or_else(|| anyhow!("component does not exist"))?;
@@ -236,6 +612,239 @@ impl AddCommand {
         Ok(())
     }
 
+    fn infer_source(&self, source: &str, manifest_file: &Path) -> Result<ComponentSource> {
+        let path = PathBuf::from(source);
+        if path.exists() {
+            return Ok(ComponentSource::Local(LocalAddCommand { path }));
+        }
+
+        if let Ok(url) = Url::parse(source) {
+            if url.scheme().starts_with("http") {
+                if self.digest.is_none() && !self.http.trust_on_first_use {
+                    bail!("Digest needs to be specified for HTTP sources, or pass --trust-on-first-use.");
+                }
+                return Ok(ComponentSource::Http(HttpAddCommand {
+                    url,
+                    digest: self.digest.clone(),
+                    yes: self.yes,
+                    options: self.http.clone(),
+                    network: self.network.clone(),
+                    offline: self.offline,
+                    cache_verification: self.cache_verification,
+                }));
+            }
+        }
+
+        if let Ok((name, version)) = package_name_ver(source) {
+            if version.is_none() {
+                bail!("Version needs to specified for registry sources.")
+            }
+            return Ok(ComponentSource::Registry(RegistryAddCommand {
+                package: name,
+                version: version.unwrap(),
+                registry: self.registry.clone(),
+                network: self.network.clone(),
+                offline: self.offline,
+                cache_verification: self.cache_verification,
+                digest: None,
+                release: None,
+                app_lockfile: if self.offline {
+                    AppLockfile::load(manifest_file)?
+                } else {
+                    AppLockfile::default()
+                },
+            }));
+        }
+
+        bail!("Could not infer component source");
+    }
+
+    /// Reports the host capabilities the dependency uses, and asks for
+    /// confirmation if it needs any that the component does not pass on to it.
+    fn check_capabilities(
+        &self,
+        resolve: &Resolve,
+        world_id: WorldId,
+        component_id: &str,
+        component: &spin_manifest::schema::v2::Component,
+    ) -> Result<()> {
+        let capabilities = imported_capabilities(resolve, world_id);
+        if capabilities.is_empty() {
+            return Ok(());
+        }
+
+        println!("The dependency uses these host capabilities:");
+        for capability in &capabilities {
+            println!("  {capability}");
+        }
+
+        let escalated = capabilities
+            .iter()
+            .filter(|c| !c.is_granted_by(component))
+            .collect::<Vec<_>>();
+        if escalated.is_empty() {
+            return Ok(());
+        }
+
+        println!("Component '{component_id}' does not grant its dependencies:");
+        for capability in &escalated {
+            println!("  {capability} (`{}`)", capability.manifest_field());
+        }
+        if !component.dependencies_inherit_configuration {
+            println!("Dependencies only receive the component's configuration if it sets `dependencies_inherit_configuration = true`.");
+        }
+        if !self.yes && !confirm_prompt("Add the dependency anyway?", false)? {
+            bail!(
+                "The dependency needs capabilities that component '{component_id}' does not grant"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Chooses the world whose exports are offered for import.
+    ///
+    /// A component's world is one of the worlds of its package. For a WIT
+    /// package, a world narrows the offer to the interfaces it exports, and
+    /// without one every interface of the package is offered.
+    fn select_world(
+        &self,
+        resolve: &mut Resolve,
+        package: PackageId,
+        kind: SourceKind,
+    ) -> Result<WorldId> {
+        let worlds = resolve.packages[package]
+            .worlds
+            .iter()
+            .map(|(name, id)| (name.clone(), *id))
+            .collect::<Vec<_>>();
+
+        if let Some(name) = &self.world {
+            let world_id = resolve
+                .select_world(package, Some(name))
+                .with_context(|| format!("World '{name}' not found in the source"))?;
+            return match kind {
+                SourceKind::Component => Ok(world_id),
+                SourceKind::WitPackage => {
+                    export_package_interfaces(resolve, package, Some(world_id))
+                }
+            };
+        }
+
+        match kind {
+            SourceKind::Component => match worlds.as_slice() {
+                [] => bail!("The component does not define a world"),
+                [(_, world_id)] => Ok(*world_id),
+                _ => {
+                    let names = worlds
+                        .iter()
+                        .map(|(name, _)| name.clone())
+                        .collect::<Vec<_>>();
+                    let index = select_prompt("Select a world to import from", &names, Some(0))?;
+                    Ok(worlds[index].1)
+                }
+            },
+            SourceKind::WitPackage => {
+                if worlds.is_empty() {
+                    return export_package_interfaces(resolve, package, None);
+                }
+                let names = std::iter::once("(All interfaces in the package)".to_owned())
+                    .chain(worlds.iter().map(|(name, _)| name.clone()))
+                    .collect::<Vec<_>>();
+                let index =
+                    select_prompt("Select a world to import the exports of", &names, Some(0))?;
+                let world_id = index.checked_sub(1).map(|i| worlds[i].1);
+                export_package_interfaces(resolve, package, world_id)
+            }
+        }
+    }
+
+    /// Fetches the source and decodes the WIT it exports.
+    async fn decode_source(
+        &self,
+        source: &mut ComponentSource,
+    ) -> Result<(Resolve, PackageId, SourceKind)> {
+        if let ComponentSource::Local(cmd) = source {
+            if cmd.is_wit() {
+                let (resolve, main) = parse_wit_path(&cmd.path, &self.feature_gates())?;
+                return Ok((resolve, main, SourceKind::WitPackage));
+            }
+        }
+
+        let mut bytes = source.get_component().await?;
+        if is_core_module(&bytes) {
+            bytes = match source {
+                ComponentSource::Local(cmd) => {
+                    self.componentize_local(&mut cmd.path, Path::new(""), &bytes)
+                        .await?
+                }
+                // Components built for `wasm32-wasip1` are core modules
+                // with their WIT embedded, so they can be componentized the
+                // same way. The copy is not rebuilt by `spin build`.
+                ComponentSource::AppComponent(cmd) => {
+                    let component = self
+                        .componentize_local(&mut cmd.path, &cmd.root_dir, &bytes)
+                        .await?;
+                    println!(
+                        "Run `spin deps add --from-component {}` again after rebuilding the component to update the dependency",
+                        cmd.component_id
+                    );
+                    component
+                }
+                ComponentSource::Http(_) | ComponentSource::Registry(_) => bail!(
+                    "Core Wasm modules can only be componentized from local sources or components of this app"
+                ),
+            };
+        }
+
+        let (mut resolve, main, kind) = parse_component_bytes(bytes)?;
+        self.feature_gates().apply(&mut resolve);
+        Ok((resolve, main, kind))
+    }
+
+    fn feature_gates(&self) -> FeatureGates {
+        FeatureGates {
+            features: self.features.clone(),
+            all_features: self.all_features,
+        }
+    }
+
+    /// Componentizes a local core module, writing the component next to it
+    /// and pointing the source path, relative to `base_dir`, at the new file.
+    async fn componentize_local(
+        &self,
+        path: &mut PathBuf,
+        base_dir: &Path,
+        module: &[u8],
+    ) -> Result<Vec<u8>> {
+        let output = path.with_extension("component.wasm");
+        let prompt = format!(
+            "{} is a core Wasm module. Componentize it to {}?",
+            base_dir.join(&path).display(),
+            base_dir.join(&output).display()
+        );
+        if !self.componentize && !confirm_prompt(&prompt, true)? {
+            bail!("Only components can be added as dependencies");
+        }
+
+        let adapter = match &self.adapter {
+            Some(path) => fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read adapter {}", path.display()))?,
+            None => WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER.to_vec(),
+        };
+        let component = componentize(module, &adapter)?;
+
+        let output_path = base_dir.join(&output);
+        fs::write(&output_path, &component)
+            .await
+            .with_context(|| format!("Failed to write component to {}", output_path.display()))?;
+        println!("Wrote component to {}", output_path.display());
+        *path = output;
+
+        Ok(component)
+    }
+
     fn target_component(&self, manifest: &AppManifest) -> anyhow::Result<String> {
         if let Some(id) = &self.add_to_component {
             return Ok(id.to_owned());
@@ -255,14 +864,13 @@ impl AddCommand {
     /// Prompts the user to select an interface to import.
     fn select_interfaces(
         &self,
-        resolve: &mut Resolve,
-        main: PackageId,
-    ) -> Result<HashMap<wit_parser::PackageName, Vec<String>>> {
-        let world_id = resolve.select_world(main, None)?;
+        resolve: &Resolve,
+        world_id: WorldId,
+    ) -> Result<HashMap<wit_parser::PackageName, PackageSelection>> {
         let exported_interfaces = get_exported_interfaces(resolve, world_id);
 
         if exported_interfaces.is_empty() {
-            bail!("No exported interfaces found in the component")
+            return Ok(HashMap::new());
         };
 
         let mut package_interface_map: HashMap<_, Vec<String>> = HashMap::new();
@@ -288,7 +896,6 @@ impl AddCommand {
             let package_name = &package_names[package_idx];
             let interfaces = package_interface_map.get(package_name).unwrap();
             let interface_count = interfaces.len();
-            let mut selected_interfaces = Vec::new();
 
             // If there's only one interface, skip the "Import all" option
             let interface_options: Vec<String> = if interface_count > 1 {
@@ -309,72 +916,348 @@ impl AddCommand {
                 Some(0),
             )?;
 
-            if interface_count > 1 && selected_interface_idx == 0 {
-                selected_interfaces.push(package_name.to_string());
+            let selection = if interface_count > 1 && selected_interface_idx == 0 {
+                // The whole package is bound, including interfaces it only
+                // uses internally, since any of them may appear in the
+                // exported ones.
+                let package_id = resolve.package_names[package_name];
+                PackageSelection {
+                    whole_package: true,
+                    interfaces: resolve.packages[package_id]
+                        .interfaces
+                        .keys()
+                        .map(|itf_name| qualified_itf_name(package_name, itf_name))
+                        .collect(),
+                }
             } else {
                 let interface_name = &interface_options[selected_interface_idx];
-                let full_itf_name = qualified_itf_name(package_name, interface_name);
-                selected_interfaces.push(full_itf_name);
-            }
+                PackageSelection {
+                    whole_package: false,
+                    interfaces: vec![qualified_itf_name(package_name, interface_name)],
+                }
+            };
+            selected_interface_map.insert(package_name.clone(), selection);
+        }
+
+        Ok(selected_interface_map)
+    }
+
+    /// Prompts the user to select the functions and unqualified interfaces to
+    /// import, if the component exports any under plain names.
+    fn select_plain_exports(&self, plain_exports: &[String]) -> Result<Vec<String>> {
+        if plain_exports.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let selected_indices = select_multiple_prompt(
+            "Select functions and unqualified interfaces to import (use space to select, enter to confirm)",
+            plain_exports,
+        )?;
+
+        Ok(selected_indices
+            .into_iter()
+            .map(|idx| plain_exports[idx].clone())
+            .collect())
+    }
 
-            if !selected_interfaces.is_empty() {
-                selected_interface_map.insert(package_name.clone(), selected_interfaces);
+    /// Adds a dependency to the component's dependencies, resolving any clash
+    /// with an existing entry. The names of entries it takes the place of are
+    /// added to `removed`.
+    fn insert_dependency(
+        &self,
+        dependencies: &mut ComponentDependencies,
+        component_id: &str,
+        name: DependencyName,
+        package: &str,
+        dependency: ComponentDependency,
+        removed: &mut Vec<DependencyName>,
+    ) -> Result<Insertion> {
+        if let DependencyName::Package(new) = &name {
+            if let Some(covering) = covering_package_dependency(dependencies, new, &dependency) {
+                println!(
+                    "Component '{component_id}' already imports `{name}` through `{covering}`"
+                );
+                return Ok(Insertion::Covered);
+            }
+            if new.interface.is_none() {
+                let folded = folded_interface_dependencies(dependencies, new, &dependency);
+                for folded_name in folded {
+                    dependencies.inner.shift_remove(&folded_name);
+                    println!("Folded `{folded_name}` into `{name}`");
+                    removed.push(folded_name);
+                }
             }
         }
 
-        Ok(selected_interface_map)
+        let Some(existing) = clashing_dependency(dependencies, &name) else {
+            dependencies.inner.insert(name.clone(), dependency);
+            return Ok(Insertion::Added(name));
+        };
+
+        println!(
+            "Component '{component_id}' already has dependency `{existing}` ({}), which `{name}` would replace",
+            describe_dependency(&dependencies.inner[&existing])
+        );
+
+        // A whole package cannot be imported under a single plain name.
+        let renamable = !matches!(&name, DependencyName::Package(p) if p.interface.is_none());
+        let choice = if self.force {
+            Clash::Replace
+        } else {
+            let mut options = vec![(Clash::Replace, format!("Replace `{existing}`"))];
+            if renamable {
+                options.push((
+                    Clash::Rename,
+                    format!("Add `{name}` under a different name"),
+                ));
+            }
+            options.push((Clash::Skip, format!("Skip `{name}`")));
+            let labels = options
+                .iter()
+                .map(|(_, label)| label.clone())
+                .collect::<Vec<_>>();
+            let index = select_prompt(
+                "How should the clash be resolved?",
+                &labels,
+                Some(labels.len() - 1),
+            )?;
+            options[index].0
+        };
+
+        match choice {
+            Clash::Replace => {
+                dependencies.inner.shift_remove(&existing);
+                dependencies.inner.insert(name.clone(), dependency);
+                removed.push(existing);
+                Ok(Insertion::Added(name))
+            }
+            Clash::Rename => {
+                let new_name = input_prompt("Dependency name", |input: &String| {
+                    let id = KebabId::try_from(input.clone())?;
+                    if dependencies.inner.contains_key(&DependencyName::Plain(id)) {
+                        return Err(format!("`{input}` is already a dependency"));
+                    }
+                    Ok(())
+                })?;
+                let new_name =
+                    DependencyName::Plain(KebabId::try_from(new_name).map_err(|e| anyhow!("{e}"))?);
+                let export = dependency_export(&dependency)
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| name.to_string());
+                let dependency = with_export(dependency, package, export);
+                dependencies.inner.insert(new_name.clone(), dependency);
+                println!(
+                    "Recording `{name}` as `{new_name}`; its bindings import it under that name."
+                );
+                Ok(Insertion::Added(new_name))
+            }
+            Clash::Skip => Ok(Insertion::Skipped),
+        }
     }
 
-    /// Updates the manifest file with the new component dependency.
+    /// Writes the component's new dependencies to the manifest file.
     async fn update_manifest(
         &self,
-        source: ComponentSource,
+        source: &ComponentSource,
+        kind: SourceKind,
         manifest_file: &Path,
-        manifest: &mut AppManifest,
         selected_component: &str,
-        selected_interfaces: &[String],
+        dependencies: &ComponentDependencies,
+        dependency_names: &[DependencyName],
     ) -> Result<()> {
-        let id = KebabId::try_from(selected_component.to_owned()).unwrap();
-        let component = manifest.components.get_mut(&id).unwrap();
-
-        let component_dependency = match source {
-            ComponentSource::Local(src) => ComponentDependency::Local {
-                path: src.path.clone(),
-                export: None,
-            },
-            ComponentSource::Http(src) => ComponentDependency::HTTP {
-                url: src.url.to_string(),
-                digest: format!("sha256:{}", src.digest.clone()),
-                export: None,
-            },
-            ComponentSource::Registry(src) => ComponentDependency::Package {
-                version: src.version.to_string(),
-                registry: src.registry.as_ref().map(|registry| registry.to_string()),
-                package: Some(src.package.clone().to_string()),
-                export: None,
-            },
-        };
-
-        for interface in selected_interfaces {
-            component.dependencies.inner.insert(
-                DependencyName::Package(DependencyPackageName::try_from(interface.clone())?),
-                component_dependency.clone(),
+        if kind == SourceKind::WitPackage {
+            println!(
+                "WARNING: The source only describes the selected interfaces, so placeholder dependencies were recorded. \
+                They point at files under {SPIN_WIT_DIRECTORY}/placeholders that do not exist, so `spin build` and \
+                `spin up` fail until they are pointed at an implementing component."
             );
         }
 
-        let doc = edit_component_deps_in_manifest(
-            manifest_file,
-            selected_component,
-            &component.dependencies,
-        )
-        .await?;
+        let doc = edit_component_deps_in_manifest(manifest_file, selected_component, dependencies)
+            .await?;
 
         fs::write(manifest_file, doc).await?;
 
+        if let (Some((digest, description)), SourceKind::Component) = (source.cached_digest(), kind)
+        {
+            let dependency_names = dependency_names
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            record_cache_references(
+                &digest,
+                &description,
+                manifest_file,
+                selected_component,
+                &dependency_names,
+            )
+            .await?;
+        }
+
         Ok(())
     }
 }
 
+/// Locks the added dependencies to the fetched component in the app's
+/// lockfile, and forgets the ones they replaced. Dependencies on sources that
+/// are not fetched are not locked.
+fn update_lockfile(
+    source: &ComponentSource,
+    kind: SourceKind,
+    manifest_file: &Path,
+    component: &str,
+    dependency_names: &[DependencyName],
+    removed: &[DependencyName],
+) -> Result<()> {
+    let mut lockfile = AppLockfile::load(manifest_file)?;
+    for name in removed.iter().chain(dependency_names) {
+        lockfile.remove(component, &name.to_string());
+    }
+    if kind == SourceKind::Component {
+        for name in dependency_names {
+            if let Some(locked) = source.locked(component, &name.to_string()) {
+                lockfile.lock(locked);
+            }
+        }
+    }
+    lockfile.save(manifest_file)
+}
+
+/// What became of a selection once clashes with existing dependencies were
+/// resolved.
+enum Insertion {
+    /// The dependency was recorded under the given name.
+    Added(DependencyName),
+    /// An existing dependency already provides it.
+    Covered,
+    /// The user chose not to add it.
+    Skipped,
+}
+
+/// The ways a clash with an existing dependency can be resolved.
+#[derive(Clone, Copy)]
+enum Clash {
+    Replace,
+    Rename,
+    Skip,
+}
+
+/// Records which dependencies use a cached component, so that `spin deps cache`
+/// can tell what the component is used by.
+async fn record_cache_references(
+    digest: &str,
+    source: &str,
+    manifest_file: &Path,
+    component: &str,
+    dependency_names: &[String],
+) -> Result<()> {
+    let cache = Cache::new(None).await?;
+    let manifest = absolute_manifest_path(manifest_file);
+    let mut index = ReferenceIndex::load(&cache).await?;
+    for dependency in dependency_names {
+        index.record(
+            digest,
+            CacheReference {
+                manifest: manifest.clone(),
+                component: component.to_owned(),
+                dependency: dependency.clone(),
+                source: source.to_owned(),
+            },
+        );
+    }
+    index.save(&cache).await
+}
+
+/// Finds an existing package-level dependency on the same component that
+/// already provides the interface or package.
+fn covering_package_dependency(
+    dependencies: &ComponentDependencies,
+    name: &DependencyPackageName,
+    dependency: &ComponentDependency,
+) -> Option<DependencyName> {
+    dependencies
+        .inner
+        .iter()
+        .find(|(existing, existing_dependency)| match existing {
+            DependencyName::Package(p) => {
+                p.interface.is_none()
+                    && p.package == name.package
+                    && p.version == name.version
+                    && same_source(existing_dependency, dependency)
+            }
+            DependencyName::Plain(_) => false,
+        })
+        .map(|(existing, _)| existing.clone())
+}
+
+/// Finds the interface-level dependencies on the same component that a new
+/// package-level dependency takes the place of.
+fn folded_interface_dependencies(
+    dependencies: &ComponentDependencies,
+    package: &DependencyPackageName,
+    dependency: &ComponentDependency,
+) -> Vec<DependencyName> {
+    dependencies
+        .inner
+        .iter()
+        .filter(|(existing, existing_dependency)| match existing {
+            DependencyName::Package(p) => {
+                p.interface.is_some()
+                    && p.package == package.package
+                    && p.version == package.version
+                    && same_source(existing_dependency, dependency)
+            }
+            DependencyName::Plain(_) => false,
+        })
+        .map(|(existing, _)| existing.clone())
+        .collect()
+}
+
+/// Finds an existing dependency that the new one would replace: one with the
+/// same name, or one covering the same package-level or interface-level import.
+fn clashing_dependency(
+    dependencies: &ComponentDependencies,
+    name: &DependencyName,
+) -> Option<DependencyName> {
+    dependencies
+        .inner
+        .keys()
+        .find(|existing| match (existing, name) {
+            (DependencyName::Package(a), DependencyName::Package(b)) => {
+                a.package == b.package
+                    && (a.version.is_none() || b.version.is_none() || a.version == b.version)
+                    && (a.interface.is_none()
+                        || b.interface.is_none()
+                        || a.interface == b.interface)
+            }
+            (a, b) => a == b,
+        })
+        .cloned()
+}
+
+fn parse_kebab_id(name: &str) -> Result<KebabId> {
+    KebabId::try_from(name.to_owned()).map_err(|e| anyhow!("Invalid name '{name}': {e}"))
+}
+
+/// A dependency to stand in until an implementation of the interface exists.
+///
+/// It points at a local file that is never written, so that Spin fails with an
+/// error naming the placeholder. A registry version would instead make Spin
+/// look the package up in the default registry, where an unrelated package
+/// of the same name may exist.
+fn placeholder_dependency(name: &DependencyPackageName) -> ComponentDependency {
+    let mut file_name = format!("{}-{}", name.package.namespace(), name.package.name());
+    if let Some(version) = &name.version {
+        file_name.push_str(&format!("-{version}"));
+    }
+    ComponentDependency::Local {
+        path: PathBuf::from(SPIN_WIT_DIRECTORY)
+            .join("placeholders")
+            .join(format!("{file_name}.wasm")),
+        export: None,
+    }
+}
+
 fn package_name_ver(package_name: &str) -> Result<(PackageRef, Option<VersionReq>)> {
     let (package, version) = package_name
         .split_once('@')
@@ -389,6 +1272,66 @@ fn package_name_ver(package_name: &str) -> Result<(PackageRef, Option<VersionReq
     Ok((package.parse()?, version))
 }
 
+/// The qualified names of the interfaces and every interface they use types
+/// from, which are what bindings have to be generated for. Interfaces the
+/// SDK already binds are still listed, so that the generated bindings reuse
+/// the SDK's types for them.
+fn qualified_closure(
+    resolve: &Resolve,
+    roots: impl IntoIterator<Item = InterfaceId>,
+) -> Vec<String> {
+    interface_closure(resolve, roots)
+        .into_iter()
+        .filter_map(|id| resolve.id_of(id))
+        .collect()
+}
+
+/// The interfaces the bindings module of a package has to bind. The module
+/// is named for the package and rewritten as a whole, so besides the
+/// selected interfaces it covers those of the component's existing
+/// dependencies on the same package.
+fn module_interfaces(
+    resolve: &Resolve,
+    dependencies: &ComponentDependencies,
+    package: &wit_parser::PackageName,
+    selected: &[String],
+) -> Vec<String> {
+    let mut bound = selected.to_vec();
+    for name in dependencies.inner.keys() {
+        let DependencyName::Package(existing) = name else {
+            continue;
+        };
+        let same_package = existing.package.namespace().to_string() == package.namespace
+            && existing.package.name().to_string() == package.name
+            && existing.version == package.version;
+        if !same_package {
+            continue;
+        }
+        match &existing.interface {
+            Some(_) => bound.push(name.to_string()),
+            None => bound.extend(
+                resolve
+                    .package_names
+                    .get(package)
+                    .into_iter()
+                    .flat_map(|id| {
+                        resolve.packages[*id]
+                            .interfaces
+                            .keys()
+                            .map(|itf_name| qualified_itf_name(package, itf_name))
+                    }),
+            ),
+        }
+    }
+
+    let roots = resolve
+        .interfaces
+        .iter()
+        .map(|(id, _)| id)
+        .filter(|id| resolve.id_of(*id).is_some_and(|name| bound.contains(&name)));
+    qualified_closure(resolve, roots)
+}
+
 fn qualified_itf_name(package_name: &wit_parser::PackageName, interface_name: &str) -> String {
     if let Some(version) = package_name.version.as_ref() {
         format!(
@@ -407,11 +1350,18 @@ struct BindOMatic<'a> {
     package_name: &'a wit_parser::PackageName,
     resolve: &'a wit_parser::Resolve,
     interfaces: &'a [String],
-    rel_wit_path: &'a Path,
+    worlds: &'a [String],
+    /// The names the plain world imports selected exports under.
+    plain_imports: &'a [String],
+    features: &'a [String],
+    docs: bool,
+    wit_paths: &'a [PathBuf],
 }
 
 enum Language {
-    Rust,
+    Rust {
+        cargo_toml: PathBuf,
+    },
     #[allow(dead_code)] // for now
     TypeScript {
         package_json: PathBuf,
@@ -438,7 +1388,7 @@ impl BindOMatic<'_> {
 
         let cargo_toml = build_dir.join("Cargo.toml");
         if cargo_toml.is_file() {
-            return Ok(Language::Rust);
+            return Ok(Language::Rust { cargo_toml });
         }
         let package_json = build_dir.join("package.json");
         if package_json.is_file() {
@@ -450,21 +1400,64 @@ impl BindOMatic<'_> {
     }
 }
 
-async fn try_generate_bindings<'a>(target: &'a BindOMatic<'a>) -> anyhow::Result<()> {
-    match target.try_infer_language()? {
-        Language::Rust => {
-            generate_rust_bindings(
-                target.root_dir,
+/// Generates bindings for the target, returning where they were written,
+/// relative to the application directory.
+async fn try_generate_bindings<'a>(target: &'a BindOMatic<'a>) -> anyhow::Result<PathBuf> {
+    let language = target.try_infer_language()?;
+
+    let async_functions = async_functions(target.resolve, target.interfaces);
+    if !async_functions.is_empty() {
+        println!(
+            "These functions pass futures or streams, so they are bound as async functions. \
+            The component must be built with async support to call them:"
+        );
+        for function in &async_functions {
+            println!("  {function}");
+        }
+    }
+
+    match language {
+        Language::Rust { cargo_toml } => {
+            let crate_dir = cargo_toml.parent().unwrap_or(target.root_dir);
+            let wit_bindgen = sdk_wit_bindgen_version(crate_dir);
+            let support = MacroSupport::of(wit_bindgen.as_ref());
+            if let Some(version) = &wit_bindgen {
+                if !support.features && !target.features.is_empty() {
+                    println!(
+                        "WARNING: The component's Spin SDK uses wit-bindgen {version}, which cannot enable WIT features, \
+                        so the bindings leave out items gated behind {}. Update spin-sdk to one using wit-bindgen \
+                        {FEATURES_SINCE} or later to bind them.",
+                        target.features.join(", ")
+                    );
+                }
+                if !support.async_imports && !generated_async_functions(&async_functions).is_empty()
+                {
+                    println!(
+                        "WARNING: The component's Spin SDK uses wit-bindgen {version}, which cannot bind functions \
+                        that pass streams or futures, so the bindings do not build until spin-sdk is updated to one \
+                        using wit-bindgen {ASYNC_IMPORTS_SINCE} or later."
+                    );
+                }
+            }
+            let gen_macro = rust_bindings_macro(
                 target.package_name,
                 target.interfaces,
-                target.rel_wit_path,
-            )
-            .await
+                target.worlds,
+                target.features,
+                &async_functions,
+                target.wit_paths,
+                support,
+            );
+            generate_rust_bindings(target.root_dir, target.package_name, &gen_macro).await
         }
         Language::TypeScript { package_json: _ } => {
             generate_ts_bindings(
                 target.root_dir,
                 target.package_name,
+                target.worlds,
+                target.plain_imports,
+                &async_functions,
+                target.docs,
                 &mut target.resolve.clone(),
             )
             .await
@@ -475,8 +1468,12 @@ async fn try_generate_bindings<'a>(target: &'a BindOMatic<'a>) -> anyhow::Result
 async fn generate_ts_bindings(
     root_dir: &Path,
     package_name: &wit_parser::PackageName,
+    worlds: &[String],
+    plain_imports: &[String],
+    async_functions: &[String],
+    docs: bool,
     resolve: &mut Resolve,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<PathBuf> {
     println!(
         "Generating TypeScript bindings for {}/{}",
         package_name.namespace, package_name.name
@@ -494,19 +1491,29 @@ async fn generate_ts_bindings(
         .unwrap()
         .0;
 
-    let world_id = resolve.select_world(package_id, Some("root"))?;
-
-    let out_world_name = &format!(
-        "importized-{}-{}",
-        package_name.namespace, package_name.name
-    )
-    .replace("_", "-")
-    .replace(":", "-")
-    .replace("@", "")
-    .replace("/", "-");
-
-    resolve.importize(world_id, Some(out_world_name.clone()))?;
-    let out_world_id = resolve.select_world(package_id, Some(out_world_name))?;
+    // A world that imports plain-named exports already has the imports to
+    // bind; otherwise the component's own world is turned around.
+    let (world_id, out_world_id, out_world_name) = match worlds.first() {
+        Some(world) => {
+            let world_name = world.rsplit_once('/').map_or(world.as_str(), |(_, w)| w);
+            let world_id = resolve.select_world(package_id, Some(world_name))?;
+            (world_id, world_id, world_name.to_owned())
+        }
+        None => {
+            let world_id = resolve.select_world(package_id, Some("root"))?;
+            let out_world_name = format!(
+                "importized-{}-{}",
+                package_name.namespace, package_name.name
+            )
+            .replace("_", "-")
+            .replace(":", "-")
+            .replace("@", "")
+            .replace("/", "-");
+            resolve.importize(world_id, Some(out_world_name.clone()))?;
+            let out_world_id = resolve.select_world(package_id, Some(out_world_name.as_str()))?;
+            (world_id, out_world_id, out_world_name)
+        }
+    };
 
     // Create a new directory within the spin component working directory
     let package_dir = root_dir.join(&package_name_str);
@@ -516,7 +1523,7 @@ async fn generate_ts_bindings(
     let package_json = package_dir.join("package.json");
     let package_json_content = package_json_content(
         &package_name_str,
-        out_world_name,
+        &out_world_name,
         package_name.version.clone(),
     );
     fs::write(&package_json, package_json_content)
@@ -532,7 +1539,8 @@ async fn generate_ts_bindings(
     let world_wit = package_dir.join("wit/world.wit");
     // create if not exist
     fs::create_dir_all(world_wit.parent().unwrap()).await?;
-    let world_wit_text = resolve_to_wit(resolve, package_id).context("failed to resolve to wit")?;
+    let world_wit_text =
+        resolve_to_wit(resolve, package_id, docs).context("failed to resolve to wit")?;
     fs::write(&world_wit, world_wit_text)
         .await
         .context("No wit folder")?;
@@ -550,7 +1558,15 @@ async fn generate_ts_bindings(
             map: None,
             no_nodejs_compat: false,
             base64_cutoff: 0,
-            async_mode: None,
+            // Async imports return promises. Resources need nothing extra:
+            // they are classes whose handles move with `own` parameters and
+            // stay with the caller for `borrow` ones.
+            async_mode: (!async_functions.is_empty()).then(|| {
+                AsyncMode::JavaScriptPromiseIntegration {
+                    imports: async_functions.to_vec(),
+                    exports: vec![],
+                }
+            }),
             tla_compat: false,
             valid_lifting_optimization: false,
             tracing: false,
@@ -577,9 +1593,17 @@ async fn generate_ts_bindings(
     let mut re_exports: Vec<String> = Vec::new();
     re_exports.push(reference_types.join("\n"));
     let mut name_counts: HashMap<String, usize> = HashMap::new();
-    for (_, item) in resolve.worlds[out_world_id].imports.iter() {
-        match item {
-            wit_parser::WorldItem::Interface { id, stability: _ } => {
+    for (key, item) in resolve.worlds[out_world_id].imports.iter() {
+        match (key, item) {
+            (WorldKey::Name(name), wit_parser::WorldItem::Interface { .. }) => {
+                if !plain_imports.contains(name) {
+                    continue;
+                }
+                let iface_name = name.to_case(Case::Camel);
+                re_exports.push(format!("import * as {iface_name} from '{name}';"));
+                re_exports.push(format!("export {{ {iface_name} }};"));
+            }
+            (_, wit_parser::WorldItem::Interface { id, stability: _ }) => {
                 let iface = &resolve.interfaces[*id];
 
                 let iface_name = &iface.name.clone().unwrap().to_case(Case::Camel);
@@ -607,10 +1631,19 @@ async fn generate_ts_bindings(
                 ));
                 re_exports.push(format!("export {{ {} }};", final_name));
             }
-            // TODO: spin deps itself does not importing functions
-            wit_parser::WorldItem::Function(_) => {}
+            (_, wit_parser::WorldItem::Function(func)) => {
+                if !plain_imports.contains(&func.name) {
+                    continue;
+                }
+                let function_name = func.name.to_case(Case::Camel);
+                re_exports.push(format!(
+                    "import {{ default as {} }} from '{}';",
+                    function_name, func.name
+                ));
+                re_exports.push(format!("export {{ {} }};", function_name));
+            }
             // Types are not generated by the TypeScript bindings generator
-            wit_parser::WorldItem::Type(_) => {}
+            (_, wit_parser::WorldItem::Type(_)) => {}
         }
     }
     let index_js = package_dir.join("index.js");
@@ -623,25 +1656,41 @@ async fn generate_ts_bindings(
         package_name_str
     );
 
-    Ok(())
+    Ok(PathBuf::from(package_name_str))
 }
 
-async fn generate_rust_bindings(
-    root_dir: &Path,
+/// The functions of the async ones that the generated bindings make async.
+/// Only functions of interfaces generated here can be; the SDK decides for
+/// the interfaces it binds.
+fn generated_async_functions(async_functions: &[String]) -> Vec<&String> {
+    async_functions
+        .iter()
+        .filter(|f| {
+            let interface = f.split_once('#').map_or(f.as_str(), |(i, _)| i);
+            !crate::language::rust::is_stdlib_known(interface)
+                && !crate::language::rust::is_sdk_known(interface)
+        })
+        .collect()
+}
+
+/// The `generate!` call that binds the interfaces and worlds, leaving out the
+/// options the SDK's wit-bindgen does not accept. The WIT is always read from
+/// a list of paths, which the wit-bindgen 0.16 of older SDKs such as
+/// `spin-sdk` 3.0.1 does not accept.
+fn rust_bindings_macro(
     package_name: &wit_parser::PackageName,
     interfaces: &[String],
-    rel_wit_path: &Path,
-) -> anyhow::Result<()> {
-    // now set up the bindings
-    let deps_rs_dir = root_dir.join("src/deps");
-    fs::create_dir_all(&deps_rs_dir).await?;
-    let dep_module_name = crate::language::rust::identifier_safe(package_name);
-
-    // step 1: create a module with the generate! macro
+    worlds: &[String],
+    features: &[String],
+    async_functions: &[String],
+    wit_paths: &[PathBuf],
+    support: MacroSupport,
+) -> String {
     let imps = interfaces
         .iter()
         .filter(|itf| !crate::language::rust::is_stdlib_known(itf))
         .map(|i| format!(r#"        import {i};"#))
+        .chain(worlds.iter().map(|w| format!(r#"        include {w};"#)))
         .collect::<Vec<_>>();
     let imps = imps.join("\n");
     let gens = interfaces
@@ -663,16 +1712,52 @@ async fn generate_rust_bindings(
         .collect::<Vec<_>>();
     let gens = gens.join("\n");
     let gen_name = format!("{}-{}", package_name.namespace, package_name.name);
+    let dep_paths = wit_paths
+        .iter()
+        .map(|p| format!("{:?}", p.display().to_string()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let features = if features.is_empty() || !support.features {
+        String::new()
+    } else {
+        let features = features
+            .iter()
+            .map(|f| format!("\"{f}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("    features: [{features}],\n")
+    };
+    let async_imports = generated_async_functions(async_functions)
+        .into_iter()
+        .map(|f| format!("\"import:{f}\""))
+        .collect::<Vec<_>>();
+    let async_imports = if async_imports.is_empty() || !support.async_imports {
+        String::new()
+    } else {
+        format!("    async: [{}],\n", async_imports.join(", "))
+    };
 
-    let binding_file = deps_rs_dir.join(format!("{dep_module_name}.rs"));
-    let gen_macro = include_str!("gen.txt")
-        .replace(
-            "{!dep_path!}",
-            format!("{}", rel_wit_path.display()).as_str(),
-        )
+    include_str!("gen.txt")
+        .replace("{!dep_paths!}", &dep_paths)
         .replace("{!imps!}", &imps)
         .replace("{!gens!}", &gens)
-        .replace("{!gen_name!}", &gen_name);
+        .replace("{!gen_name!}", &gen_name)
+        .replace("{!features!}", &features)
+        .replace("{!async!}", &async_imports)
+}
+
+async fn generate_rust_bindings(
+    root_dir: &Path,
+    package_name: &wit_parser::PackageName,
+    gen_macro: &str,
+) -> anyhow::Result<PathBuf> {
+    // now set up the bindings
+    let deps_rs_dir = root_dir.join("src/deps");
+    fs::create_dir_all(&deps_rs_dir).await?;
+    let dep_module_name = crate::language::rust::identifier_safe(package_name);
+
+    // step 1: create a module with the generate! macro
+    let binding_file = deps_rs_dir.join(format!("{dep_module_name}.rs"));
     fs::write(&binding_file, gen_macro).await?;
 
     // step 2: add it to mod.rs
@@ -719,6 +1804,30 @@ async fn generate_rust_bindings(
         }
     }
 
+    Ok(PathBuf::from("src/deps").join(format!("{dep_module_name}.rs")))
+}
+
+/// Removes bindings generated for a dependency: a Rust module along with its
+/// declaration, or a TypeScript package directory.
+async fn remove_bindings(root_dir: &Path, bindings: &Path) -> anyhow::Result<()> {
+    let path = root_dir.join(bindings);
+    if path.is_dir() {
+        fs::remove_dir_all(&path).await?;
+    } else if path.is_file() {
+        fs::remove_file(&path).await?;
+        let mod_rs_file = path.with_file_name("mod.rs");
+        if let (Some(module), true) = (path.file_stem(), mod_rs_file.is_file()) {
+            let dep_module_decl = format!("pub mod {};", module.to_string_lossy());
+            let mod_rs = fs::read_to_string(&mod_rs_file).await?;
+            let mod_rs = mod_rs
+                .lines()
+                .filter(|line| line.trim() != dep_module_decl)
+                .map(|line| format!("{line}\n"))
+                .collect::<String>();
+            fs::write(&mod_rs_file, mod_rs).await?;
+        }
+    }
+    println!("Removed bindings {}", bindings.display());
     Ok(())
 }
 
@@ -771,3 +1880,283 @@ fn tsconfig_content() -> String {
 }"#
     .to_owned()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::wit::package_to_wit;
+    use wit_parser::{Function, Handle, LiveTypes, Type, TypeDefKind, TypeOwner};
+
+    const RECENT_SDK: MacroSupport = MacroSupport {
+        features: true,
+        async_imports: true,
+    };
+
+    /// Reads a fixture the way `add` reads a WIT source, returning the
+    /// resolve, the fixture's package and the world exporting its interfaces.
+    fn read_fixture(fixture: &str) -> (Resolve, PackageId, WorldId) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(fixture)
+            .join(format!("{fixture}.wit"));
+        let (mut resolve, package) = parse_wit_path(&path, &FeatureGates::default()).unwrap();
+        let provider = resolve.select_world(package, Some("provider")).unwrap();
+        let world = export_package_interfaces(&mut resolve, package, Some(provider)).unwrap();
+        (resolve, package, world)
+    }
+
+    /// The interfaces bound for a selection of the fixture's interfaces.
+    fn bound_interfaces(resolve: &Resolve, selected: &[&str]) -> Vec<String> {
+        let roots = resolve.interfaces.iter().map(|(id, _)| id).filter(|id| {
+            resolve
+                .id_of(*id)
+                .is_some_and(|name| selected.contains(&name.as_str()))
+        });
+        qualified_closure(resolve, roots)
+    }
+
+    fn bindings_macro(
+        resolve: &Resolve,
+        package: PackageId,
+        interfaces: &[String],
+        support: MacroSupport,
+    ) -> String {
+        rust_bindings_macro(
+            &resolve.packages[package].name,
+            interfaces,
+            &[],
+            &[],
+            &async_functions(resolve, interfaces),
+            &[],
+            support,
+        )
+    }
+
+    fn dependencies(manifest: &str) -> ComponentDependencies {
+        toml::from_str(manifest).unwrap()
+    }
+
+    #[test]
+    fn second_add_keeps_interfaces_of_the_first() {
+        // The first add selected `registry`, and this one selects `counters`
+        // from the same package, whose bindings share a module.
+        let (resolve, package, _) = read_fixture("resources");
+        let name = resolve.packages[package].name.clone();
+        let existing =
+            dependencies(r#""fixtures:resources/registry@0.1.0" = { path = "provider.wasm" }"#);
+        let selected = ["fixtures:resources/counters@0.1.0".to_owned()];
+
+        let interfaces = module_interfaces(&resolve, &existing, &name, &selected);
+        assert_eq!(
+            interfaces,
+            [
+                "fixtures:resources/counters@0.1.0",
+                "fixtures:resources/registry@0.1.0"
+            ]
+        );
+        let gen_macro = bindings_macro(&resolve, package, &interfaces, RECENT_SDK);
+        assert!(gen_macro.contains("        import fixtures:resources/registry@0.1.0;\n"));
+    }
+
+    #[test]
+    fn second_add_keeps_a_package_added_as_a_whole() {
+        let (resolve, package, _) = read_fixture("resources");
+        let name = resolve.packages[package].name.clone();
+        let existing = dependencies(r#""fixtures:resources@0.1.0" = { path = "provider.wasm" }"#);
+        let selected = ["fixtures:resources/counters@0.1.0".to_owned()];
+
+        let interfaces = module_interfaces(&resolve, &existing, &name, &selected);
+        assert_eq!(interfaces.len(), 2);
+    }
+
+    #[test]
+    fn module_leaves_out_other_packages() {
+        let (resolve, package, _) = read_fixture("resources");
+        let name = resolve.packages[package].name.clone();
+        let existing =
+            dependencies(r#""fixtures:streams/chunks@0.1.0" = { path = "streams.wasm" }"#);
+        let selected = ["fixtures:resources/counters@0.1.0".to_owned()];
+
+        let interfaces = module_interfaces(&resolve, &existing, &name, &selected);
+        assert_eq!(interfaces, ["fixtures:resources/counters@0.1.0"]);
+    }
+
+    #[test]
+    fn resources_bind_the_interface_that_defines_them() {
+        let (resolve, package, _) = read_fixture("resources");
+        let interfaces = bound_interfaces(&resolve, &["fixtures:resources/registry@0.1.0"]);
+        assert_eq!(
+            interfaces,
+            [
+                "fixtures:resources/counters@0.1.0",
+                "fixtures:resources/registry@0.1.0"
+            ]
+        );
+
+        let gen_macro = bindings_macro(&resolve, package, &interfaces, RECENT_SDK);
+        assert!(gen_macro.contains("        import fixtures:resources/counters@0.1.0;\n"));
+        assert!(gen_macro.contains("        import fixtures:resources/registry@0.1.0;\n"));
+        assert!(gen_macro.contains(r#"        "fixtures:resources/counters@0.1.0": generate,"#));
+        assert!(gen_macro.contains(r#"        "fixtures:resources/registry@0.1.0": generate,"#));
+        assert!(!gen_macro.contains("async:"));
+    }
+
+    /// The handle a type passes, looking through options, with the resource
+    /// it is a handle to looked up through `use`s.
+    fn passed_handle(resolve: &Resolve, ty: Type) -> Handle {
+        let Type::Id(id) = ty else {
+            panic!("{ty:?} is not a handle");
+        };
+        let dealias = |mut id| {
+            while let TypeDefKind::Type(Type::Id(target)) = resolve.types[id].kind {
+                id = target;
+            }
+            id
+        };
+        match resolve.types[id].kind {
+            TypeDefKind::Option(ty) => passed_handle(resolve, ty),
+            TypeDefKind::Handle(Handle::Own(resource)) => Handle::Own(dealias(resource)),
+            TypeDefKind::Handle(Handle::Borrow(resource)) => Handle::Borrow(dealias(resource)),
+            ref kind => panic!("{kind:?} is not a handle"),
+        }
+    }
+
+    #[test]
+    fn resources_keep_their_handle_ownership() {
+        // The deps tree written for the fixture, read back the way the
+        // bindings read it.
+        let (resolve, _, world) = read_fixture("resources");
+        let main = resolve.worlds[world].package.unwrap();
+        let deps_dir =
+            std::env::temp_dir().join(format!("spin-deps-test-handles-{}", std::process::id()));
+        let dirs = write_deps_tree(&resolve, &deps_dir, &[main], false).unwrap();
+        let mut written = Resolve::default();
+        for dir in &dirs {
+            written.push_dir(dir).unwrap();
+        }
+        std::fs::remove_dir_all(&deps_dir).unwrap();
+
+        let interface = |name: &str| {
+            written
+                .interfaces
+                .iter()
+                .find(|(_, i)| i.name.as_deref() == Some(name))
+                .map(|(_, i)| i)
+                .unwrap()
+        };
+        let (counters, registry) = (interface("counters"), interface("registry"));
+        let counter = counters.types["counter"];
+        let param = |f: &Function, i: usize| passed_handle(&written, f.params[i].1);
+        let result = |f: &Function| passed_handle(&written, f.result.unwrap());
+
+        let finish = &counters.functions["[static]counter.finish"];
+        assert_eq!(param(finish, 0), Handle::Own(counter));
+        let merge = &counters.functions["[static]counter.merge"];
+        assert_eq!(param(merge, 0), Handle::Borrow(counter));
+        assert_eq!(param(merge, 1), Handle::Borrow(counter));
+        assert_eq!(result(merge), Handle::Own(counter));
+        assert_eq!(
+            param(&registry.functions["peek"], 1),
+            Handle::Borrow(counter)
+        );
+        assert_eq!(result(&registry.functions["take"]), Handle::Own(counter));
+    }
+
+    #[test]
+    fn streams_bind_only_their_functions_as_async() {
+        let (resolve, package, _) = read_fixture("streams");
+        let interfaces = bound_interfaces(&resolve, &["fixtures:streams/chunks@0.1.0"]);
+
+        let gen_macro = bindings_macro(&resolve, package, &interfaces, RECENT_SDK);
+        assert!(gen_macro.contains(
+            r#"    async: ["import:fixtures:streams/chunks@0.1.0#read-all", "import:fixtures:streams/chunks@0.1.0#store"],"#
+        ));
+        assert!(!gen_macro.contains("#count"));
+    }
+
+    #[test]
+    fn streams_leave_out_async_for_an_old_sdk() {
+        let (resolve, package, _) = read_fixture("streams");
+        let interfaces = bound_interfaces(&resolve, &["fixtures:streams/chunks@0.1.0"]);
+        let example_sdk = MacroSupport::of(Some(&Version::new(0, 16, 0)));
+
+        let gen_macro = bindings_macro(&resolve, package, &interfaces, example_sdk);
+        assert!(!gen_macro.contains("async:"));
+        assert!(gen_macro.contains(r#"        "fixtures:streams/chunks@0.1.0": generate,"#));
+    }
+
+    #[test]
+    fn renamed_interface_is_imported_under_its_name() {
+        let (mut resolve, package, world) = read_fixture("resources");
+        let imports = [(
+            "my-counters".to_owned(),
+            "fixtures:resources/counters@0.1.0".to_owned(),
+        )];
+        let plain_world =
+            add_plain_import_world(&mut resolve, world, &imports, "exports-test").unwrap();
+        let main = resolve.worlds[plain_world].package.unwrap();
+
+        let wit = package_to_wit(&resolve, main, false).unwrap();
+        assert!(wit.contains("import my-counters: interface {"));
+        assert!(
+            wit.contains("merge: static func(a: borrow<counter>, b: borrow<counter>) -> counter;")
+        );
+        assert!(!wit.contains("import fixtures:resources/counters@0.1.0;"));
+
+        // The copy is WIT that reads back along with the fixture.
+        let mut reread = Resolve::default();
+        reread
+            .push_str(
+                "resources.wit",
+                &package_to_wit(&resolve, package, false).unwrap(),
+            )
+            .unwrap();
+        reread.push_str("root.wit", &wit).unwrap();
+    }
+
+    #[test]
+    fn renamed_interface_uses_only_its_own_types() {
+        let (mut resolve, _, world) = read_fixture("resources");
+        let counters = resolve
+            .interfaces
+            .iter()
+            .find(|(_, i)| i.name.as_deref() == Some("counters"))
+            .map(|(id, _)| id)
+            .unwrap();
+        let imports = [
+            (
+                "my-counters".to_owned(),
+                "fixtures:resources/counters@0.1.0".to_owned(),
+            ),
+            (
+                "my-registry".to_owned(),
+                "fixtures:resources/registry@0.1.0".to_owned(),
+            ),
+        ];
+        let plain_world =
+            add_plain_import_world(&mut resolve, world, &imports, "exports-test").unwrap();
+        resolve.assert_valid();
+
+        let world = &resolve.worlds[plain_world];
+        let copy = match &world.imports[&WorldKey::Name("my-counters".to_owned())] {
+            WorldItem::Interface { id, .. } => *id,
+            _ => panic!("my-counters is not an interface"),
+        };
+        // The registry still uses the original resource, which is imported.
+        assert!(world.imports.contains_key(&WorldKey::Interface(counters)));
+
+        let mut live = LiveTypes::default();
+        live.add_interface(&resolve, copy);
+        assert!(live
+            .iter()
+            .all(|ty| resolve.types[ty].owner != TypeOwner::Interface(counters)));
+        let merge = &resolve.interfaces[copy].functions["[static]counter.merge"];
+        let Type::Id(borrow) = merge.params[0].1 else {
+            panic!("merge takes a handle");
+        };
+        let TypeDefKind::Handle(Handle::Borrow(counter)) = resolve.types[borrow].kind else {
+            panic!("merge takes a borrow");
+        };
+        assert_eq!(resolve.interfaces[copy].types["counter"], counter);
+    }
+}
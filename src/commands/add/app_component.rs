@@ -0,0 +1,114 @@
+use anyhow::{anyhow, bail, Context, Result};
+use spin_manifest::schema::v2::AppManifest;
+use spin_serde::KebabId;
+use std::path::{Path, PathBuf};
+use tokio::{fs, process::Command};
+
+/// Command to add another component of the same application.
+#[derive(Debug)]
+pub struct AppComponentAddCommand {
+    /// The ID of the component in the application manifest.
+    pub component_id: String,
+    /// The component's Wasm source, as written in the manifest.
+    pub path: PathBuf,
+    /// The directory containing the application manifest.
+    pub root_dir: PathBuf,
+    /// The commands that build the component's Wasm source.
+    pub build_commands: Vec<String>,
+    /// The directory the build commands run in.
+    pub build_dir: PathBuf,
+}
+
+impl AppComponentAddCommand {
+    pub fn new(manifest: &AppManifest, root_dir: &Path, component_id: &str) -> Result<Self> {
+        let id = KebabId::try_from(component_id.to_owned()).map_err(|e| anyhow!("{e}"))?;
+        let component = manifest
+            .components
+            .get(&id)
+            .with_context(|| format!("Component '{component_id}' does not exist"))?;
+
+        let path = match &component.source {
+            spin_manifest::schema::v2::ComponentSource::Local(path) => PathBuf::from(path),
+            _ => bail!(
+                "Component '{component_id}' does not have a local source. Add it from its URL or registry reference instead."
+            ),
+        };
+
+        let build_commands = component
+            .build
+            .as_ref()
+            .map(|b| b.commands().cloned().collect())
+            .unwrap_or_default();
+        let build_dir = match component.build.as_ref().and_then(|b| b.workdir.as_ref()) {
+            None => root_dir.to_owned(),
+            Some(d) => root_dir.join(d),
+        };
+
+        Ok(Self {
+            component_id: component_id.to_owned(),
+            path,
+            root_dir: root_dir.to_owned(),
+            build_commands,
+            build_dir,
+        })
+    }
+
+    pub async fn get_component(&self) -> Result<Vec<u8>> {
+        let wasm_path = self.root_dir.join(&self.path);
+        if !wasm_path.exists() {
+            self.build().await?;
+        }
+
+        let bytes = fs::read(&wasm_path).await.with_context(|| {
+            format!(
+                "Failed to read Wasm source of component '{}' from {}",
+                self.component_id,
+                wasm_path.display()
+            )
+        })?;
+
+        Ok(bytes)
+    }
+
+    /// Runs the component's build commands to produce its Wasm source.
+    async fn build(&self) -> Result<()> {
+        if self.build_commands.is_empty() {
+            bail!(
+                "Source {} of component '{}' does not exist and the component has no build command",
+                self.path.display(),
+                self.component_id
+            );
+        }
+
+        println!("Building component '{}'", self.component_id);
+        for command in &self.build_commands {
+            let status = shell_command(command)
+                .current_dir(&self.build_dir)
+                .status()
+                .await
+                .with_context(|| format!("Failed to run build command `{command}`"))?;
+            if !status.success() {
+                bail!(
+                    "Build command `{command}` for component '{}' failed ({status})",
+                    self.component_id
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn shell_command(command: &str) -> Command {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(command);
+    cmd
+}
@@ -1,47 +1,554 @@
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use clap::Args;
-use reqwest::Client;
-use sha2::{Digest, Sha256};
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::{
+    header::{ETAG, IF_RANGE, LAST_MODIFIED, LOCATION, RANGE},
+    redirect::Policy,
+    Client, Response, StatusCode,
+};
 use spin_loader::cache::Cache;
-use tokio::fs;
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs, io::AsyncWriteExt};
 use url::Url;
 
+use crate::common::{
+    auth::HttpAuth,
+    cache::{read_cached, CacheVerification, PARTIAL_SUFFIX},
+    digest::{Algorithm, ContentDigest, ContentDigests, ContentHasher},
+    interact::confirm_prompt,
+    network::NetworkOptions,
+};
+
+const DEFAULT_MAX_DOWNLOAD_SIZE: u64 = 512 * 1024 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_REDIRECTS: usize = 10;
+
+/// Options for downloading HTTP sources.
+#[derive(Args, Clone, Debug)]
+pub struct HttpOptions {
+    /// Download HTTP sources without a known digest, and record the digest of the downloaded
+    /// content in the manifest after confirming it.
+    #[clap(long, conflicts_with = "digest")]
+    pub trust_on_first_use: bool,
+    /// The maximum size, in bytes, of an HTTP download.
+    #[clap(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_DOWNLOAD_SIZE)]
+    pub max_download_size: u64,
+    /// How many times to retry an HTTP download that failed for a transient reason.
+    #[clap(long, value_name = "COUNT", default_value_t = 3)]
+    pub download_retries: u32,
+    #[clap(flatten)]
+    pub auth: HttpAuth,
+}
+
 /// Command to add a component from an HTTP source.
 #[derive(Args, Debug)]
 pub struct HttpAddCommand {
     /// The HTTP URL of the component .
     pub url: Url,
-    /// The digest for verifying the integrity of the component. The digest must be a SHA-256 hash.
+    /// The digest for verifying the integrity of the component. This may be a SHA-256 or SHA-512
+    /// hash, as bare hex, `<algorithm>:<hex>` or Subresource Integrity `<algorithm>-<base64>`.
+    /// If omitted, the digest of the downloaded content is trusted on first use.
+    #[clap(short, long)]
+    pub digest: Option<ContentDigest>,
+    /// Trust the digest of the downloaded content without asking for confirmation.
     #[clap(short, long)]
-    pub digest: String,
+    pub yes: bool,
+    #[clap(flatten)]
+    pub options: HttpOptions,
+    #[clap(flatten)]
+    pub network: NetworkOptions,
+    /// Only use the component if it is already in the cache, without downloading it.
+    #[clap(long)]
+    pub offline: bool,
+    /// How to check a cached component before using it.
+    #[clap(long, value_enum, default_value_t)]
+    pub cache_verification: CacheVerification,
+}
+
+/// Why a download attempt failed, and so whether it is worth retrying.
+enum DownloadError {
+    Transient(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() {
+            Self::Transient(e.into())
+        } else {
+            Self::Fatal(e.into())
+        }
+    }
+}
+
+impl From<std::io::Error> for DownloadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Fatal(e.into())
+    }
 }
 
 impl HttpAddCommand {
-    pub async fn get_component(&self) -> Result<Vec<u8>> {
+    pub async fn get_component(&mut self) -> Result<Vec<u8>> {
         let cache = Cache::new(None).await?;
-        let digest = format!("sha256:{}", &self.digest);
-        if let Ok(path) = cache.wasm_file(&digest) {
-            return fs::read(path).await.map_err(|e| anyhow!(e));
+        if let Some(digest) = &self.digest {
+            // The cache is keyed by SHA-256, so other digests always download.
+            if digest.algorithm() == Algorithm::Sha256 {
+                let cached =
+                    read_cached(&cache, &digest.to_string(), self.cache_verification).await?;
+                if let Some(bytes) = cached {
+                    return Ok(bytes);
+                }
+            }
         }
 
-        let client = Client::new();
-        let response = client.get(self.url.clone()).send().await?;
-        if !response.status().is_success() {
-            bail!("Failed to fetch component from {}", response.url());
+        if self.offline {
+            bail!(
+                "{} is not in the cache; run without --offline to download it",
+                self.url
+            );
         }
 
-        let bytes = response.bytes().await?;
-        let mut hasher = Sha256::new();
-        hasher.update(&bytes);
-        let actual_digest = format!("sha256:{:x}", hasher.finalize());
-        ensure!(
-            actual_digest == digest,
-            "invalid content digest; expected {digest}, downloaded {actual_digest}"
+        cache.ensure_dirs().await?;
+        // Interrupted downloads are kept here, keyed by URL, so a later
+        // attempt can resume them.
+        let partial = cache.wasm_dir().join(format!(
+            "{}{PARTIAL_SUFFIX}",
+            ContentDigest::of(Algorithm::Sha256, self.url.as_str().as_bytes()).hex()
+        ));
+
+        let client = self.client()?;
+
+        let mut attempt = 0;
+        let digests = loop {
+            match self.download(&client, &partial).await {
+                Ok(digests) => break digests,
+                Err(DownloadError::Transient(e)) if attempt < self.options.download_retries => {
+                    attempt += 1;
+                    let delay = Duration::from_secs(1 << (attempt - 1));
+                    eprintln!(
+                        "Download of {} failed ({e:#}); retrying in {}s",
+                        self.url,
+                        delay.as_secs()
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(DownloadError::Transient(e) | DownloadError::Fatal(e)) => return Err(e),
+            }
+        };
+
+        let verified = match &self.digest {
+            Some(digest) => digests.verify(digest),
+            None => self.confirm_digest(&digests.sha256),
+        };
+        if let Err(e) = verified {
+            discard_partial(&partial).await?;
+            return Err(e);
+        }
+
+        // Spin verifies HTTP dependencies by SHA-256, so that is the digest
+        // recorded in the manifest whichever algorithm was used to verify.
+        let dest = cache.wasm_path(digests.sha256.to_string());
+        fs::rename(&partial, &dest)
+            .await
+            .context("Failed to save download to cache")?;
+        discard_partial(&partial).await?;
+        self.digest = Some(digests.sha256);
+
+        Ok(fs::read(dest).await?)
+    }
+
+    fn client(&self) -> Result<Client> {
+        Ok(self
+            .network
+            .http_client_builder()?
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT)
+            .redirect(Policy::none())
+            .build()?)
+    }
+
+    /// Streams the component into the partial file, resuming from whatever it
+    /// already holds, and returns the digests of the complete content.
+    ///
+    /// A download is only resumed if the server identified the content with
+    /// a validator when it was started. The validator is sent back in
+    /// `If-Range`, so that if the content has changed since, the server sends
+    /// all of the new content rather than the rest of it.
+    async fn download(
+        &self,
+        client: &Client,
+        partial: &Path,
+    ) -> Result<ContentDigests, DownloadError> {
+        let mut hasher = ContentHasher::default();
+        let mut offset = 0;
+        let validator = fs::read_to_string(validator_path(partial)).await.ok();
+        match &validator {
+            Some(_) if partial.is_file() => {
+                let existing = fs::read(partial).await?;
+                hasher.update(&existing);
+                offset = existing.len() as u64;
+            }
+            _ => discard_partial(partial).await?,
+        }
+
+        let url = self
+            .network
+            .rewrite_url(&self.url)
+            .map_err(DownloadError::Fatal)?;
+        let (url, mut response) = self.send(client, url, offset, validator.as_deref()).await?;
+
+        let status = response.status();
+        let mut file = if offset > 0 && status == StatusCode::PARTIAL_CONTENT {
+            fs::OpenOptions::new().append(true).open(partial).await?
+        } else if status.is_success() {
+            // The server sent the whole content, so start again.
+            hasher = ContentHasher::default();
+            offset = 0;
+            let file = fs::File::create(partial).await?;
+            match resume_validator(&response) {
+                Some(validator) => fs::write(validator_path(partial), validator).await?,
+                None => remove_if_exists(&validator_path(partial)).await?,
+            }
+            file
+        } else if status == StatusCode::RANGE_NOT_SATISFIABLE {
+            discard_partial(partial).await?;
+            return Err(DownloadError::Transient(anyhow!(
+                "server could not resume the download"
+            )));
+        } else {
+            let e = anyhow!("Failed to fetch component from {url} ({status})");
+            let transient = status.is_server_error()
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || status == StatusCode::REQUEST_TIMEOUT;
+            return Err(if transient {
+                DownloadError::Transient(e)
+            } else {
+                DownloadError::Fatal(e)
+            });
+        };
+
+        let max_size = self.options.max_download_size;
+        let total = response.content_length().map(|len| len + offset);
+        if total.is_some_and(|total| total > max_size) {
+            drop(file);
+            discard_partial(partial).await?;
+            return Err(DownloadError::Fatal(size_error(&self.url, max_size)));
+        }
+
+        let progress = progress_bar(&self.url, total, offset);
+        while let Some(chunk) = response.chunk().await? {
+            offset += chunk.len() as u64;
+            if offset > max_size {
+                drop(file);
+                discard_partial(partial).await?;
+                return Err(DownloadError::Fatal(size_error(&self.url, max_size)));
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            progress.set_position(offset);
+        }
+        file.flush().await?;
+        progress.finish_and_clear();
+
+        Ok(hasher.finalize())
+    }
+
+    /// Requests the content from the given offset, following redirects here
+    /// rather than in the client so that credentials are only ever sent to
+    /// the hosts they are for. Returns the final URL and its response.
+    async fn send(
+        &self,
+        client: &Client,
+        mut url: Url,
+        offset: u64,
+        validator: Option<&str>,
+    ) -> Result<(Url, Response), DownloadError> {
+        for _ in 0..MAX_REDIRECTS {
+            let mut request = client.get(url.clone());
+            if let (true, Some(validator)) = (offset > 0, validator) {
+                request = request
+                    .header(RANGE, format!("bytes={offset}-"))
+                    .header(IF_RANGE, validator);
+            }
+            let request = self
+                .options
+                .auth
+                .apply(request, &url, &self.url)
+                .map_err(DownloadError::Fatal)?;
+            let response = request.send().await?;
+            if !response.status().is_redirection() {
+                return Ok((url, response));
+            }
+
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| {
+                    DownloadError::Fatal(anyhow!("Redirect from {url} has no valid location"))
+                })?;
+            url = url.join(location).map_err(|e| {
+                DownloadError::Fatal(anyhow!("Invalid redirect from {url} to '{location}': {e}"))
+            })?;
+        }
+        Err(DownloadError::Fatal(anyhow!(
+            "Too many redirects fetching {}",
+            self.url
+        )))
+    }
+
+    /// Shows the digest of content downloaded without a known digest and asks
+    /// whether to trust it.
+    fn confirm_digest(&self, digest: &ContentDigest) -> Result<()> {
+        println!("Downloaded {} with digest {digest}", self.url);
+        let trusted =
+            self.yes || confirm_prompt("Trust this digest and record it in the manifest?", false)?;
+        if !trusted {
+            bail!("Digest of {} was not trusted", self.url);
+        }
+        Ok(())
+    }
+}
+
+/// The file holding the validator of a partial download. It is itself named
+/// as a partial download, so that it is cleaned up along with them.
+fn validator_path(partial: &Path) -> PathBuf {
+    partial.with_extension(format!("validator{PARTIAL_SUFFIX}"))
+}
+
+/// The validator to resume the response's content with: a strong `ETag`,
+/// or failing that `Last-Modified`. Weak `ETag`s cannot be used in
+/// `If-Range`.
+fn resume_validator(response: &Response) -> Option<String> {
+    let headers = response.headers();
+    headers
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| {
+            headers
+                .get(LAST_MODIFIED)
+                .and_then(|modified| modified.to_str().ok())
+        })
+        .map(str::to_owned)
+}
+
+/// Removes a partial download and its validator.
+async fn discard_partial(partial: &Path) -> std::io::Result<()> {
+    remove_if_exists(partial).await?;
+    remove_if_exists(&validator_path(partial)).await
+}
+
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn size_error(url: &Url, max_size: u64) -> anyhow::Error {
+    anyhow!("{url} is larger than the maximum download size of {max_size} bytes (see --max-download-size)")
+}
+
+/// A progress bar for a download, shown only when stderr is a terminal.
+fn progress_bar(url: &Url, total: Option<u64>, position: u64) -> ProgressBar {
+    if !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let (progress, template) = match total {
+        Some(total) => (
+            ProgressBar::new(total),
+            "{msg} [{bar:30}] {bytes}/{total_bytes} ({eta})",
+        ),
+        None => (ProgressBar::new_spinner(), "{msg} {spinner} {bytes}"),
+    };
+    let style =
+        ProgressStyle::with_template(template).unwrap_or_else(|_| ProgressStyle::default_bar());
+    progress.set_style(style);
+    progress.set_message(format!("Downloading {url}"));
+    progress.set_position(position);
+    progress
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    /// Serves one request with the response, and returns the head of the
+    /// request, lowercased.
+    async fn serve_once(listener: TcpListener, response: String) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+        stream.write_all(response.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+        String::from_utf8(request).unwrap().to_ascii_lowercase()
+    }
+
+    fn command(url: Url, headers: &[&str]) -> HttpAddCommand {
+        HttpAddCommand {
+            url,
+            digest: None,
+            yes: true,
+            options: HttpOptions {
+                trust_on_first_use: true,
+                max_download_size: DEFAULT_MAX_DOWNLOAD_SIZE,
+                download_retries: 0,
+                auth: HttpAuth {
+                    headers: headers.iter().map(|h| h.to_string()).collect(),
+                    no_netrc: true,
+                },
+            },
+            network: NetworkOptions::default(),
+            offline: false,
+            cache_verification: CacheVerification::default(),
+        }
+    }
+
+    /// A partial download path of its own for a test, with nothing in it.
+    async fn partial_path(test: &str) -> PathBuf {
+        let partial = std::env::temp_dir().join(format!(
+            "spin-deps-test-{test}-{}{PARTIAL_SUFFIX}",
+            std::process::id()
+        ));
+        discard_partial(&partial).await.unwrap();
+        partial
+    }
+
+    /// Downloads from a server that answers one request with the response,
+    /// starting from the partial content and validator, if any. Returns the
+    /// request the server received and the digest of the content.
+    async fn download_with_partial(
+        test: &str,
+        existing: Option<(&str, &str)>,
+        response: &str,
+    ) -> (String, ContentDigest, PathBuf) {
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://127.0.0.1:{}/c.wasm",
+            server.local_addr().unwrap().port()
         );
+        let request = tokio::spawn(serve_once(server, response.to_owned()));
+
+        let partial = partial_path(test).await;
+        if let Some((content, validator)) = existing {
+            fs::write(&partial, content).await.unwrap();
+            if !validator.is_empty() {
+                fs::write(validator_path(&partial), validator)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let cmd = command(Url::parse(&url).unwrap(), &[]);
+        let Ok(digests) = cmd.download(&cmd.client().unwrap(), &partial).await else {
+            panic!("download failed");
+        };
+        (request.await.unwrap(), digests.sha256, partial)
+    }
 
-        let dest = cache.wasm_path(digest);
-        fs::write(dest, &bytes).await?;
+    #[tokio::test]
+    async fn resumes_with_if_range() {
+        let (request, digest, partial) = download_with_partial(
+            "resume",
+            Some(("hel", "\"v1\"")),
+            "HTTP/1.1 206 Partial Content\r\nContent-Length: 2\r\nConnection: close\r\n\r\nlo",
+        )
+        .await;
+        discard_partial(&partial).await.unwrap();
+
+        assert!(request.contains("range: bytes=3-"));
+        assert!(request.contains("if-range: \"v1\""));
+        assert_eq!(digest, ContentDigest::of(Algorithm::Sha256, b"hello"));
+    }
+
+    #[tokio::test]
+    async fn restarts_when_content_changed() {
+        let (request, digest, partial) = download_with_partial(
+            "changed",
+            Some(("hel", "\"v1\"")),
+            "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 6\r\nConnection: close\r\n\r\nworld!",
+        )
+        .await;
+        let validator = fs::read_to_string(validator_path(&partial)).await.unwrap();
+        discard_partial(&partial).await.unwrap();
+
+        assert!(request.contains("if-range: \"v1\""));
+        assert_eq!(digest, ContentDigest::of(Algorithm::Sha256, b"world!"));
+        assert_eq!(validator, "\"v2\"");
+    }
 
-        Ok(bytes.to_vec())
+    #[tokio::test]
+    async fn does_not_resume_without_validator() {
+        let (request, digest, partial) = download_with_partial(
+            "no-validator",
+            Some(("hel", "")),
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+        )
+        .await;
+        let validator_written = validator_path(&partial).exists();
+        discard_partial(&partial).await.unwrap();
+
+        assert!(!request.contains("range:"));
+        assert!(!validator_written);
+        assert_eq!(digest, ContentDigest::of(Algorithm::Sha256, b"hello"));
+    }
+
+    #[tokio::test]
+    async fn headers_are_only_sent_to_their_host_across_redirects() {
+        let source = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let source_url = format!(
+            "http://127.0.0.1:{}/c.wasm",
+            source.local_addr().unwrap().port()
+        );
+        let target_url = format!(
+            "http://localhost:{}/c.wasm",
+            target.local_addr().unwrap().port()
+        );
+
+        let source_request = tokio::spawn(serve_once(
+            source,
+            format!("HTTP/1.1 302 Found\r\nLocation: {target_url}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+        ));
+        let target_request = tokio::spawn(serve_once(
+            target,
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello".to_owned(),
+        ));
+
+        let cmd = command(
+            Url::parse(&source_url).unwrap(),
+            &["X-Api-Key: secret", "localhost=X-Target: yes"],
+        );
+        let partial = partial_path("redirects").await;
+        let Ok(digests) = cmd.download(&cmd.client().unwrap(), &partial).await else {
+            panic!("download failed");
+        };
+        discard_partial(&partial).await.unwrap();
+
+        assert_eq!(
+            digests.sha256,
+            ContentDigest::of(Algorithm::Sha256, b"hello")
+        );
+        let source_request = source_request.await.unwrap();
+        let target_request = target_request.await.unwrap();
+        assert!(source_request.contains("x-api-key: secret"));
+        assert!(!source_request.contains("x-target"));
+        assert!(target_request.contains("x-target: yes"));
+        assert!(!target_request.contains("x-api-key"));
     }
 }
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Args;
 use std::path::PathBuf;
 use tokio::fs;
@@ -11,9 +11,28 @@ pub struct LocalAddCommand {
 }
 
 impl LocalAddCommand {
+    /// Whether the path refers to WIT text (a `.wit` file or a directory of
+    /// them) rather than to a Wasm binary.
+    pub fn is_wit(&self) -> bool {
+        self.path.is_dir() || self.path.extension().is_some_and(|ext| ext == "wit")
+    }
+
+    /// Whether the path refers to a component in the WebAssembly text format.
+    fn is_wat(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|ext| ext == "wat" || ext == "wast")
+    }
+
     pub async fn get_component(&self) -> Result<Vec<u8>> {
         let bytes = fs::read(&self.path).await?;
 
+        if self.is_wat() {
+            let binary = wat::parse_bytes(&bytes)
+                .with_context(|| format!("Failed to parse {}", self.path.display()))?;
+            return Ok(binary.into_owned());
+        }
+
         Ok(bytes)
     }
 }
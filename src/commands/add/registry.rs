@@ -1,11 +1,19 @@
 use anyhow::{Context, Result};
 use clap::Args;
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
 use semver::VersionReq;
 use spin_loader::cache::Cache;
-use tokio::io::AsyncWriteExt;
+use std::path::Path;
+use tokio::{fs, io::AsyncWriteExt};
 use wasm_pkg_common::{package::PackageRef, registry::Registry};
 
+use crate::common::{
+    cache::{read_cached, temp_path, CacheVerification, RegistryIndex},
+    digest::{ContentDigest, ContentHasher},
+    lockfile::AppLockfile,
+    network::NetworkOptions,
+};
+
 /// Command to add a component from a registry.
 #[derive(Args, Debug)]
 pub struct RegistryAddCommand {
@@ -17,14 +25,44 @@ pub struct RegistryAddCommand {
     /// Optional registry to specify where to fetch the package from.
     #[clap(short, long)]
     pub registry: Option<Registry>,
+    #[clap(flatten)]
+    pub network: NetworkOptions,
+    /// Resolve the version from releases already fetched into the cache, without contacting
+    /// the registry.
+    #[clap(long)]
+    pub offline: bool,
+    /// How to check a cached release before using it.
+    #[clap(long, value_enum, default_value_t)]
+    pub cache_verification: CacheVerification,
+    /// The digest of the fetched release, once it has been fetched.
+    #[clap(skip)]
+    pub digest: Option<String>,
+    /// The version of the fetched release, once it has been fetched.
+    #[clap(skip)]
+    pub release: Option<String>,
+    /// The lockfile of the app the component is added to, whose releases
+    /// offline resolution prefers.
+    #[clap(skip)]
+    pub app_lockfile: AppLockfile,
 }
 
 impl RegistryAddCommand {
-    pub async fn get_component(&self) -> Result<Vec<u8>> {
+    pub async fn get_component(&mut self) -> Result<Vec<u8>> {
+        let cache = Cache::new(None).await?;
+        if self.offline {
+            return self.get_cached_component(&cache).await;
+        }
+
+        self.network.check_registry_support()?;
         let mut client_config = wasm_pkg_client::Config::global_defaults()?;
 
-        if let Some(registry) = &self.registry {
-            client_config.set_package_registry_override(self.package.clone(), registry.to_owned());
+        let registry = match &self.registry {
+            Some(registry) => Some(registry.to_owned()),
+            None => client_config.resolve_registry(&self.package).cloned(),
+        };
+        if let Some(registry) = registry {
+            let registry = self.network.rewrite_registry(&registry)?;
+            client_config.set_package_registry_override(self.package.clone(), registry);
         }
 
         let pkg_loader = wasm_pkg_client::Client::new(client_config);
@@ -52,26 +90,109 @@ impl RegistryAddCommand {
             wasm_pkg_client::ContentDigest::Sha256 { hex } => format!("sha256:{hex}"),
         };
 
-        let cache = Cache::new(None).await?;
-        let path = if let Ok(cached_path) = cache.wasm_file(&digest) {
-            cached_path
-        } else {
-            let mut stm = pkg_loader.stream_content(&self.package, &release).await?;
-
-            cache.ensure_dirs().await?;
-            let dest = cache.wasm_path(&digest);
-
-            let mut file = tokio::fs::File::create(&dest).await?;
-            while let Some(block) = stm.next().await {
-                let bytes = block.context("Failed to get content from registry")?;
-                file.write_all(&bytes)
+        let bytes = match read_cached(&cache, &digest, self.cache_verification).await? {
+            Some(bytes) => bytes,
+            None => {
+                let stm = pkg_loader.stream_content(&self.package, &release).await?;
+
+                cache.ensure_dirs().await?;
+                // Stream into a temporary file so an interrupted or corrupt
+                // fetch never leaves content under the digest.
+                let temp = temp_path(&cache, &digest);
+                if let Err(e) = self.stream_to_file(stm, &temp, &digest).await {
+                    if temp.exists() {
+                        fs::remove_file(&temp).await?;
+                    }
+                    return Err(e);
+                }
+
+                let dest = cache.wasm_path(&digest);
+                fs::rename(&temp, &dest)
                     .await
                     .context("Failed to save registry content to cache")?;
+                fs::read(dest).await?
             }
-
-            dest
         };
 
-        Ok(tokio::fs::read(path).await?)
+        let mut index = RegistryIndex::load(&cache).await?;
+        index.record(&self.package, &release.version, &digest);
+        index.save(&cache).await?;
+        self.digest = Some(digest);
+        self.release = Some(release.version.to_string());
+
+        Ok(bytes)
+    }
+
+    /// Writes the release content to the file, checking it against the
+    /// release's digest as it streams.
+    async fn stream_to_file<B, E>(
+        &self,
+        mut stm: impl Stream<Item = Result<B, E>> + Unpin,
+        path: &Path,
+        digest: &str,
+    ) -> Result<()>
+    where
+        B: AsRef<[u8]>,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let expected = digest.parse::<ContentDigest>()?;
+        let mut hasher = ContentHasher::default();
+
+        let mut file = fs::File::create(path).await?;
+        while let Some(block) = stm.next().await {
+            let bytes = block.context("Failed to get content from registry")?;
+            hasher.update(bytes.as_ref());
+            file.write_all(bytes.as_ref())
+                .await
+                .context("Failed to save registry content to cache")?;
+        }
+        file.flush().await?;
+
+        hasher
+            .finalize()
+            .verify(&expected)
+            .with_context(|| format!("Content of {} failed verification", &self.package))
+    }
+
+    /// Resolves the package from the releases the app's lockfile locks whose
+    /// content is cached, then from releases fetched into the cache earlier.
+    async fn get_cached_component(&mut self, cache: &Cache) -> Result<Vec<u8>> {
+        let locked = self
+            .app_lockfile
+            .releases(&self.package.to_string(), &self.version)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for locked in locked {
+            if let Some(bytes) = read_cached(cache, &locked.digest, self.cache_verification).await?
+            {
+                self.digest = Some(locked.digest);
+                self.release = locked.version;
+                return Ok(bytes);
+            }
+        }
+
+        let index = RegistryIndex::load(cache).await?;
+        let release = index
+            .resolve(&self.package, &self.version)
+            .with_context(|| {
+                format!(
+                    "No release of {} matching {} is locked by the app or was fetched by spin deps before; \
+                    run without --offline to fetch it",
+                    &self.package, &self.version
+                )
+            })?;
+        let bytes = read_cached(cache, &release.digest, self.cache_verification)
+            .await?
+            .with_context(|| {
+                format!(
+                    "Content of {}@{} is not in the cache; run without --offline to fetch it",
+                    &self.package, release.version
+                )
+            })?;
+        self.digest = Some(release.digest.clone());
+        self.release = Some(release.version.clone());
+
+        Ok(bytes)
     }
 }
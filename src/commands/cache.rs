@@ -0,0 +1,400 @@
+use anyhow::{bail, Context, Result};
+use clap::{Args, Subcommand};
+use indicatif::HumanBytes;
+use semver::{Version, VersionReq};
+use spin_loader::cache::Cache;
+use spin_manifest::{
+    manifest_from_file,
+    schema::v2::{AppManifest, ComponentDependency},
+};
+use std::collections::BTreeSet;
+use tokio::fs;
+
+use crate::common::{
+    cache::{
+        cached_components, metadata_dir, partial_downloads, CacheReference, CachedComponent,
+        ReferenceIndex, RegistryIndex,
+    },
+    digest::{Algorithm, ContentDigest},
+    interact::confirm_prompt,
+    lockfile::{AppLockfile, LockedDependency},
+    wit::{parse_component_bytes, SourceKind},
+};
+
+/// Inspect and manage the components cached for dependencies.
+#[derive(Args, Debug)]
+pub struct CacheCommand {
+    #[command(subcommand)]
+    command: CacheSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheSubcommand {
+    /// List cached components with their size and the dependencies using them
+    ///
+    /// Only dependencies added with spin deps are known; components Spin fetched
+    /// itself, or that were fetched before spin deps kept track, show no users.
+    List,
+    /// Show the details of a cached component
+    Inspect {
+        /// The digest of the component, as `sha256:<hex>` or bare hex.
+        digest: String,
+    },
+    /// Rehash cached components and report any that are corrupt
+    Verify {
+        /// Remove corrupt components from the cache.
+        #[clap(long)]
+        remove: bool,
+    },
+    /// Remove cached components that no known app's lockfile uses
+    ///
+    /// Known apps are those spin deps has added fetched dependencies to. Components Spin
+    /// fetched for other apps are removed too, and fetched again when those apps need them.
+    Prune {
+        /// Show what would be removed without removing it.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Remove the components fetched by spin deps
+    Clear {
+        /// Remove every component in the Spin cache, including those Spin fetched itself.
+        #[clap(long)]
+        all: bool,
+        /// Do not ask for confirmation.
+        #[clap(short, long)]
+        yes: bool,
+    },
+}
+
+impl CacheCommand {
+    pub async fn run(self) -> Result<()> {
+        let cache = Cache::new(None).await?;
+        match self.command {
+            CacheSubcommand::List => list(&cache).await,
+            CacheSubcommand::Inspect { digest } => inspect(&cache, &digest).await,
+            CacheSubcommand::Verify { remove } => verify(&cache, remove).await,
+            CacheSubcommand::Prune { dry_run } => prune(&cache, dry_run).await,
+            CacheSubcommand::Clear { all, yes } => clear(&cache, all, yes).await,
+        }
+    }
+}
+
+async fn list(cache: &Cache) -> Result<()> {
+    let components = cached_components(cache).await?;
+    if components.is_empty() {
+        println!("The cache is empty");
+        return Ok(());
+    }
+
+    let references = ReferenceIndex::load(cache).await?;
+    for component in &components {
+        println!("{}  {}", component.digest, HumanBytes(component.size));
+        let component_references = references.references(&component.digest);
+        if component_references.is_empty() {
+            println!("    (not used by any dependency added with spin deps)");
+        }
+        for reference in component_references {
+            print_reference(reference);
+        }
+    }
+
+    let total = components.iter().map(|c| c.size).sum::<u64>();
+    println!(
+        "\n{} components, {} in total",
+        components.len(),
+        HumanBytes(total)
+    );
+
+    Ok(())
+}
+
+async fn inspect(cache: &Cache, digest: &str) -> Result<()> {
+    let digest = digest
+        .parse::<ContentDigest>()
+        .context("Invalid digest")?
+        .to_string();
+    let path = cache
+        .wasm_file(&digest)
+        .with_context(|| format!("{digest} is not in the cache"))?;
+    let bytes = fs::read(&path).await?;
+
+    println!("Digest: {digest}");
+    println!("Path:   {}", path.display());
+    println!("Size:   {}", HumanBytes(bytes.len() as u64));
+
+    let references = ReferenceIndex::load(cache).await?;
+    println!("Used by:");
+    let component_references = references.references(&digest);
+    if component_references.is_empty() {
+        println!("    (not used by any dependency added with spin deps)");
+    }
+    for reference in component_references {
+        print_reference(reference);
+    }
+
+    match parse_component_bytes(bytes) {
+        Ok((resolve, main, SourceKind::Component)) => {
+            let world_id = resolve.select_world(main, None)?;
+            let world = &resolve.worlds[world_id];
+            println!("Imports:");
+            for key in world.imports.keys() {
+                println!("    {}", resolve.name_world_key(key));
+            }
+            println!("Exports:");
+            for key in world.exports.keys() {
+                println!("    {}", resolve.name_world_key(key));
+            }
+        }
+        Ok((resolve, main, SourceKind::WitPackage)) => {
+            let package = &resolve.packages[main];
+            println!("WIT package {}", package.name);
+            println!("Interfaces:");
+            for name in package.interfaces.keys() {
+                println!("    {name}");
+            }
+            println!("Worlds:");
+            for name in package.worlds.keys() {
+                println!("    {name}");
+            }
+        }
+        Err(e) => println!("Not a valid component: {e:#}"),
+    }
+
+    Ok(())
+}
+
+async fn verify(cache: &Cache, remove: bool) -> Result<()> {
+    let components = cached_components(cache).await?;
+
+    let mut corrupt = 0;
+    for component in &components {
+        if is_intact(component).await? {
+            continue;
+        }
+        corrupt += 1;
+        if remove {
+            fs::remove_file(&component.path).await?;
+            println!("{} is corrupt and was removed", component.digest);
+        } else {
+            println!("{} is corrupt", component.digest);
+        }
+    }
+
+    println!(
+        "Verified {} components, {corrupt} corrupt",
+        components.len()
+    );
+    if corrupt > 0 && !remove {
+        bail!("The cache contains corrupt components; run with --remove to remove them");
+    }
+
+    Ok(())
+}
+
+async fn prune(cache: &Cache, dry_run: bool) -> Result<()> {
+    let mut references = ReferenceIndex::load(cache).await?;
+    let mut registry_index = RegistryIndex::load(cache).await?;
+    let in_use = digests_in_use(&references)?;
+
+    let mut removed = Vec::new();
+    for component in cached_components(cache).await? {
+        if in_use.contains(&component.digest) {
+            continue;
+        }
+        references.set_references(&component.digest, vec![]);
+        registry_index.remove_digest(&component.digest);
+        if !dry_run {
+            fs::remove_file(&component.path).await?;
+        }
+        removed.push((component.digest, component.size));
+    }
+
+    for path in partial_downloads(cache).await? {
+        let size = fs::metadata(&path).await?.len();
+        if !dry_run {
+            fs::remove_file(&path).await?;
+        }
+        removed.push((format!("{} (interrupted download)", path.display()), size));
+    }
+
+    for (name, size) in &removed {
+        println!("{name}  {}", HumanBytes(*size));
+    }
+    let total = removed.iter().map(|(_, size)| size).sum::<u64>();
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    println!("{verb} {} entries, {}", removed.len(), HumanBytes(total));
+
+    if !dry_run {
+        references.save(cache).await?;
+        registry_index.save(cache).await?;
+    }
+
+    Ok(())
+}
+
+async fn clear(cache: &Cache, all: bool, yes: bool) -> Result<()> {
+    let prompt = if all {
+        "Remove every component in the Spin cache?"
+    } else {
+        "Remove every component fetched by spin deps from the cache?"
+    };
+    if !yes && !confirm_prompt(prompt, false)? {
+        return Ok(());
+    }
+
+    let mut paths = partial_downloads(cache).await?;
+    if all {
+        paths.extend(cached_components(cache).await?.into_iter().map(|c| c.path));
+    } else {
+        let references = ReferenceIndex::load(cache).await?;
+        let registry_index = RegistryIndex::load(cache).await?;
+        let known = references
+            .digests()
+            .chain(registry_index.digests())
+            .collect::<BTreeSet<_>>();
+        paths.extend(known.into_iter().filter_map(|d| cache.wasm_file(d).ok()));
+    }
+
+    for path in &paths {
+        fs::remove_file(path).await?;
+    }
+
+    let metadata = metadata_dir(cache);
+    if metadata.is_dir() {
+        fs::remove_dir_all(&metadata).await?;
+    }
+
+    println!("Removed {} entries", paths.len());
+
+    Ok(())
+}
+
+fn print_reference(reference: &CacheReference) {
+    println!(
+        "    {} in component '{}' of {} (from {})",
+        reference.dependency,
+        reference.component,
+        reference.manifest.display(),
+        reference.source
+    );
+}
+
+/// Whether the content of a cached component still matches its digest.
+async fn is_intact(component: &CachedComponent) -> Result<bool> {
+    let bytes = fs::read(&component.path).await?;
+    Ok(ContentDigest::of(Algorithm::Sha256, &bytes).to_string() == component.digest)
+}
+
+/// The digests of the cached components that known apps use. The apps are
+/// those spin deps has added fetched dependencies to.
+///
+/// A component is in use while an app's lockfile locks a dependency to it
+/// and the app's manifest still declares that dependency with the source it
+/// was locked from. A manifest that exists but does not parse keeps
+/// everything its lockfile locks, since it may only be broken for the
+/// moment, for example mid-edit. Apps whose dependencies were added before
+/// lockfiles were kept fall back to the uses spin deps recorded.
+fn digests_in_use(references: &ReferenceIndex) -> Result<BTreeSet<String>> {
+    let mut in_use = BTreeSet::new();
+    for manifest_file in references.manifests() {
+        if !manifest_file.is_file() {
+            continue;
+        }
+        let manifest = manifest_from_file(manifest_file).ok();
+
+        if !AppLockfile::path(manifest_file).is_file() {
+            for digest in references.digests() {
+                let is_used = references
+                    .references(digest)
+                    .iter()
+                    .filter(|r| r.manifest == manifest_file)
+                    .any(|r| {
+                        manifest.as_ref().is_none_or(|manifest| {
+                            declares(manifest, &r.component, &r.dependency, |d| {
+                                uses_cached_source(d, digest, &r.source)
+                            })
+                        })
+                    });
+                if is_used {
+                    in_use.insert(digest.to_owned());
+                }
+            }
+            continue;
+        }
+
+        let lockfile = AppLockfile::load(manifest_file)?;
+        for locked in lockfile.dependencies() {
+            let is_declared = manifest.as_ref().is_none_or(|manifest| {
+                declares(manifest, &locked.component, &locked.dependency, |d| {
+                    is_locked_source(d, locked)
+                })
+            });
+            if is_declared {
+                in_use.insert(locked.digest.clone());
+            }
+        }
+    }
+    Ok(in_use)
+}
+
+/// Whether the manifest declares the dependency of the component, with a
+/// source that passes the check.
+fn declares(
+    manifest: &AppManifest,
+    component: &str,
+    dependency: &str,
+    source: impl Fn(&ComponentDependency) -> bool,
+) -> bool {
+    manifest
+        .components
+        .iter()
+        .find(|(id, _)| id.to_string() == component)
+        .is_some_and(|(_, c)| {
+            c.dependencies
+                .inner
+                .iter()
+                .any(|(name, d)| name.to_string() == dependency && source(d))
+        })
+}
+
+/// Whether the dependency still points at the source it was locked from.
+fn is_locked_source(dependency: &ComponentDependency, locked: &LockedDependency) -> bool {
+    match dependency {
+        ComponentDependency::HTTP { url, digest, .. } => {
+            *url == locked.source && *digest == locked.digest
+        }
+        ComponentDependency::Package {
+            version,
+            package: Some(package),
+            ..
+        } => {
+            let release = locked
+                .version
+                .as_deref()
+                .and_then(|v| Version::parse(v).ok());
+            *package == locked.source
+                && release.is_some_and(|release| {
+                    VersionReq::parse(version).is_ok_and(|req| req.matches(&release))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Whether the dependency still points at the source the component was
+/// cached from.
+fn uses_cached_source(dependency: &ComponentDependency, digest: &str, source: &str) -> bool {
+    match dependency {
+        ComponentDependency::HTTP {
+            url,
+            digest: dependency_digest,
+            ..
+        } => url == source && dependency_digest == digest,
+        ComponentDependency::Package {
+            version,
+            package: Some(package),
+            ..
+        } => format!("{package}@{version}") == source,
+        _ => false,
+    }
+}
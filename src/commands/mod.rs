@@ -1,2 +1,3 @@
 pub mod add;
+pub mod cache;
 pub mod publish;
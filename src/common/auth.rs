@@ -0,0 +1,288 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    RequestBuilder,
+};
+use std::path::PathBuf;
+use url::Url;
+
+/// Prefix of the environment variables holding bearer tokens for HTTP sources.
+const TOKEN_ENV_PREFIX: &str = "SPIN_DEPS_TOKEN_";
+
+/// Credentials for HTTP sources. These are applied to requests only and are
+/// never written to the manifest.
+#[derive(Args, Clone, Debug, Default)]
+pub struct HttpAuth {
+    /// Header to send when downloading HTTP sources, as 'Name: value' or 'HOST=Name: value'.
+    /// Headers without a host are only sent to the host of the source URL, not to mirrors or
+    /// redirect targets. Can be repeated.
+    #[clap(long = "header", value_name = "[HOST=]NAME: VALUE")]
+    pub headers: Vec<String>,
+    /// Do not read credentials for HTTP sources from the netrc file.
+    #[clap(long)]
+    pub no_netrc: bool,
+}
+
+impl HttpAuth {
+    /// Adds credentials for the URL's host to a request made while fetching
+    /// the source.
+    ///
+    /// Configured headers are sent if they are for the URL's host, which for
+    /// headers without a host is the host of the source itself. A bearer token
+    /// is taken from `SPIN_DEPS_TOKEN_<HOST>` (for example
+    /// `SPIN_DEPS_TOKEN_GITHUB_COM`) or, failing that, a login from the netrc
+    /// file.
+    pub fn apply(
+        &self,
+        mut request: RequestBuilder,
+        url: &Url,
+        source: &Url,
+    ) -> Result<RequestBuilder> {
+        let Some(host) = url.host_str() else {
+            return Ok(request);
+        };
+
+        for header in &self.headers {
+            let (header_host, name, value) = parse_header(header)?;
+            let matches = match header_host {
+                Some(header_host) => host_matches(&header_host, url),
+                None => source
+                    .host_str()
+                    .is_some_and(|h| h.eq_ignore_ascii_case(host)),
+            };
+            if matches {
+                request = request.header(name, value);
+            }
+        }
+
+        if let Ok(token) = std::env::var(token_env_var(host)) {
+            return Ok(request.bearer_auth(token));
+        }
+
+        if !self.no_netrc {
+            if let Some(login) = netrc_login(host)? {
+                return Ok(request.basic_auth(login.login, login.password));
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+/// Parses a header given as 'Name: value' or 'HOST=Name: value', where HOST
+/// may have a port. Header names cannot contain '=', so the text before the
+/// first '=' is a host if it looks like one and a header follows it.
+fn parse_header(header: &str) -> Result<(Option<String>, HeaderName, HeaderValue)> {
+    let (host, header_text) = match header.split_once('=') {
+        Some((host, rest)) if is_host(host.trim()) && rest.contains(':') => {
+            (Some(host.trim().to_owned()), rest)
+        }
+        _ => (None, header),
+    };
+    let (name, value) = header_text.split_once(':').ok_or_else(|| {
+        anyhow!("Header '{header}' must be of the form 'Name: value' or 'HOST=Name: value'")
+    })?;
+    let name = HeaderName::try_from(name.trim())
+        .with_context(|| format!("Invalid header name '{}'", name.trim()))?;
+    let mut value = HeaderValue::try_from(value.trim())
+        .with_context(|| format!("Invalid value for header '{name}'"))?;
+    value.set_sensitive(true);
+    Ok((host, name, value))
+}
+
+/// Whether the text is a host name, optionally followed by ':' and a port.
+fn is_host(text: &str) -> bool {
+    let (name, port) = match text.split_once(':') {
+        Some((name, port)) => (name, Some(port)),
+        None => (text, None),
+    };
+    !name.is_empty()
+        && !name.contains(|c: char| c.is_whitespace() || c == '/')
+        && port.is_none_or(|port| !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Whether a header's host, with its port if it has one, is the URL's.
+fn host_matches(header_host: &str, url: &Url) -> bool {
+    let (name, port) = match header_host.split_once(':') {
+        Some((name, port)) => (name, port.parse::<u16>().ok()),
+        None => (header_host, None),
+    };
+    url.host_str().is_some_and(|h| h.eq_ignore_ascii_case(name))
+        && port.is_none_or(|port| url.port_or_known_default() == Some(port))
+}
+
+/// The environment variable holding the bearer token for a host.
+fn token_env_var(host: &str) -> String {
+    let host = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{TOKEN_ENV_PREFIX}{host}")
+}
+
+#[derive(Default)]
+struct NetrcEntry {
+    /// The host this entry applies to, or `None` for the `default` entry.
+    machine: Option<String>,
+    login: Option<String>,
+    password: Option<String>,
+}
+
+struct NetrcLogin {
+    login: String,
+    password: Option<String>,
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("NETRC") {
+        return Some(path.into());
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    let file_name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+    Some(PathBuf::from(home).join(file_name))
+}
+
+/// Looks up the login for a host in the netrc file, falling back to its
+/// `default` entry.
+fn netrc_login(host: &str) -> Result<Option<NetrcLogin>> {
+    let Some(path) = netrc_path().filter(|p| p.is_file()) else {
+        return Ok(None);
+    };
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let entries = parse_netrc(&text);
+    let login = entries
+        .iter()
+        .find(|e| e.machine.as_deref() == Some(host))
+        .or_else(|| entries.iter().find(|e| e.machine.is_none()))
+        .and_then(|e| {
+            Some(NetrcLogin {
+                login: e.login.clone()?,
+                password: e.password.clone(),
+            })
+        });
+
+    Ok(login)
+}
+
+fn parse_netrc(text: &str) -> Vec<NetrcEntry> {
+    let mut entries: Vec<NetrcEntry> = Vec::new();
+
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" => entries.push(NetrcEntry {
+                    machine: tokens.next().map(str::to_owned),
+                    ..Default::default()
+                }),
+                "default" => entries.push(NetrcEntry::default()),
+                "login" | "password" => {
+                    let value = tokens.next().map(str::to_owned);
+                    if let Some(entry) = entries.last_mut() {
+                        if token == "login" {
+                            entry.login = value;
+                        } else {
+                            entry.password = value;
+                        }
+                    }
+                }
+                "account" => {
+                    tokens.next();
+                }
+                "macdef" => {
+                    // Macro definitions run until the next blank line.
+                    for line in lines.by_ref() {
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                    }
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers_with_and_without_host() {
+        let (host, name, value) = parse_header("X-Api-Key: secret").unwrap();
+        assert_eq!(host, None);
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "secret");
+
+        let (host, name, value) = parse_header("files.example.com=X-Api-Key: a=b").unwrap();
+        assert_eq!(host.as_deref(), Some("files.example.com"));
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "a=b");
+
+        let (host, name, value) = parse_header("localhost:8080=X-Token: s").unwrap();
+        assert_eq!(host.as_deref(), Some("localhost:8080"));
+        assert_eq!(name, "x-token");
+        assert_eq!(value, "s");
+
+        let (host, name, value) = parse_header("X-Api-Key:a=b").unwrap();
+        assert_eq!(host, None);
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "a=b");
+
+        assert!(parse_header("no separator").is_err());
+        assert!(parse_header("Bad Name: value").is_err());
+    }
+
+    #[test]
+    fn header_hosts_match_with_their_port() {
+        let url = Url::parse("http://localhost:8080/dep.wasm").unwrap();
+        assert!(host_matches("localhost", &url));
+        assert!(host_matches("LOCALHOST:8080", &url));
+        assert!(!host_matches("localhost:9090", &url));
+        assert!(host_matches(
+            "example.com:443",
+            &Url::parse("https://example.com/dep.wasm").unwrap()
+        ));
+    }
+
+    #[test]
+    fn token_env_var_is_upper_snake_case_host() {
+        assert_eq!(token_env_var("github.com"), "SPIN_DEPS_TOKEN_GITHUB_COM");
+        assert_eq!(
+            token_env_var("my-host.example:8080"),
+            "SPIN_DEPS_TOKEN_MY_HOST_EXAMPLE_8080"
+        );
+    }
+
+    #[test]
+    fn parses_netrc_entries() {
+        let entries = parse_netrc(
+            "machine example.com login alice password secret\n\
+            machine other.example\n  login bob\n\
+            macdef init\n  machine ignored.example login mallory\n\n\
+            default login anonymous",
+        );
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].machine.as_deref(), Some("example.com"));
+        assert_eq!(entries[0].login.as_deref(), Some("alice"));
+        assert_eq!(entries[0].password.as_deref(), Some("secret"));
+        assert_eq!(entries[1].machine.as_deref(), Some("other.example"));
+        assert_eq!(entries[1].login.as_deref(), Some("bob"));
+        assert_eq!(entries[1].password, None);
+        assert_eq!(entries[2].machine, None);
+        assert_eq!(entries[2].login.as_deref(), Some("anonymous"));
+    }
+}
@@ -0,0 +1,325 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use rand::Rng;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use spin_loader::cache::Cache;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+use wasm_pkg_common::package::PackageRef;
+
+use super::digest::{Algorithm, ContentDigest};
+
+const REGISTRY_INDEX_FILE: &str = "registry-index.json";
+const REFERENCE_INDEX_FILE: &str = "references.json";
+
+/// Suffix of files holding interrupted downloads.
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// One in this many cache hits is verified when sampling.
+const VERIFICATION_SAMPLE_RATE: u32 = 10;
+
+/// How to check cached components before they are used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CacheVerification {
+    /// Rehash every cached component before using it.
+    #[default]
+    Always,
+    /// Rehash a random sample of cached components.
+    Sample,
+    /// Use cached components without rehashing them.
+    Never,
+}
+
+impl CacheVerification {
+    fn should_verify(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Sample => rand::thread_rng().gen_ratio(1, VERIFICATION_SAMPLE_RATE),
+            Self::Never => false,
+        }
+    }
+}
+
+/// Reads a component from the cache.
+///
+/// Returns `None` if the component is not cached, or if it was verified and
+/// its content no longer matches the digest. Corrupt entries are removed so
+/// the caller fetches them again.
+pub async fn read_cached(
+    cache: &Cache,
+    digest: &str,
+    verification: CacheVerification,
+) -> Result<Option<Vec<u8>>> {
+    let Ok(path) = cache.wasm_file(digest) else {
+        return Ok(None);
+    };
+    let bytes = fs::read(&path)
+        .await
+        .with_context(|| format!("Failed to read {} from the cache", path.display()))?;
+
+    if verification.should_verify()
+        && ContentDigest::of(Algorithm::Sha256, &bytes).to_string() != digest
+    {
+        eprintln!("Cached content of {digest} is corrupt; fetching it again");
+        fs::remove_file(&path).await?;
+        return Ok(None);
+    }
+
+    Ok(Some(bytes))
+}
+
+/// A temporary file in the cache directory for content being written under
+/// the digest, to be renamed into place once it is complete.
+pub fn temp_path(cache: &Cache, digest: &str) -> PathBuf {
+    let name = digest.replace(':', "_");
+    cache
+        .wasm_dir()
+        .join(format!("{name}.{}{PARTIAL_SUFFIX}", std::process::id()))
+}
+
+/// The directory for this plugin's own metadata, alongside the Spin cache's
+/// Wasm directory.
+pub fn metadata_dir(cache: &Cache) -> PathBuf {
+    cache.wasm_dir().with_file_name("spin-deps")
+}
+
+/// A release fetched from a registry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexedRelease {
+    pub version: String,
+    pub digest: String,
+}
+
+/// The registry releases that have been fetched into the cache, so that
+/// version requirements can be resolved without contacting the registry.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RegistryIndex {
+    packages: BTreeMap<String, Vec<IndexedRelease>>,
+}
+
+impl RegistryIndex {
+    pub async fn load(cache: &Cache) -> Result<Self> {
+        let path = metadata_dir(cache).join(REGISTRY_INDEX_FILE);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let json = fs::read(&path).await?;
+        serde_json::from_slice(&json)
+            .with_context(|| format!("Failed to parse registry index {}", path.display()))
+    }
+
+    pub async fn save(&self, cache: &Cache) -> Result<()> {
+        let dir = metadata_dir(cache);
+        fs::create_dir_all(&dir).await?;
+        fs::write(
+            dir.join(REGISTRY_INDEX_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .await
+        .context("Failed to save registry index")
+    }
+
+    /// Records that a release of the package has been fetched.
+    pub fn record(&mut self, package: &PackageRef, version: &Version, digest: &str) {
+        let releases = self.packages.entry(package.to_string()).or_default();
+        releases.retain(|r| r.version != version.to_string());
+        releases.push(IndexedRelease {
+            version: version.to_string(),
+            digest: digest.to_owned(),
+        });
+    }
+
+    /// Forgets every release with the given content digest.
+    pub fn remove_digest(&mut self, digest: &str) {
+        for releases in self.packages.values_mut() {
+            releases.retain(|r| r.digest != digest);
+        }
+        self.packages.retain(|_, releases| !releases.is_empty());
+    }
+
+    /// The digests of every fetched release.
+    pub fn digests(&self) -> impl Iterator<Item = &str> {
+        self.packages.values().flatten().map(|r| r.digest.as_str())
+    }
+
+    /// Finds the newest fetched release of the package matching the requirement.
+    pub fn resolve(
+        &self,
+        package: &PackageRef,
+        requirement: &VersionReq,
+    ) -> Option<&IndexedRelease> {
+        self.packages
+            .get(&package.to_string())?
+            .iter()
+            .filter_map(|r| Some((Version::parse(&r.version).ok()?, r)))
+            .filter(|(version, _)| requirement.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, r)| r)
+    }
+}
+
+/// A dependency in an application manifest that uses a cached component.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheReference {
+    /// The absolute path of the application manifest.
+    pub manifest: PathBuf,
+    /// The component declaring the dependency.
+    pub component: String,
+    /// The dependency name.
+    pub dependency: String,
+    /// Where the component was fetched from.
+    pub source: String,
+}
+
+/// The dependencies added by this plugin, by the digest of the cached
+/// component they use.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReferenceIndex {
+    references: BTreeMap<String, Vec<CacheReference>>,
+}
+
+impl ReferenceIndex {
+    pub async fn load(cache: &Cache) -> Result<Self> {
+        let path = metadata_dir(cache).join(REFERENCE_INDEX_FILE);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let json = fs::read(&path).await?;
+        serde_json::from_slice(&json)
+            .with_context(|| format!("Failed to parse reference index {}", path.display()))
+    }
+
+    pub async fn save(&self, cache: &Cache) -> Result<()> {
+        let dir = metadata_dir(cache);
+        fs::create_dir_all(&dir).await?;
+        fs::write(
+            dir.join(REFERENCE_INDEX_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .await
+        .context("Failed to save reference index")
+    }
+
+    /// Records that a dependency uses the cached component, replacing
+    /// whatever the dependency used before.
+    pub fn record(&mut self, digest: &str, reference: CacheReference) {
+        for references in self.references.values_mut() {
+            references.retain(|r| {
+                r.manifest != reference.manifest
+                    || r.component != reference.component
+                    || r.dependency != reference.dependency
+            });
+        }
+        self.references
+            .entry(digest.to_owned())
+            .or_default()
+            .push(reference);
+        self.references
+            .retain(|_, references| !references.is_empty());
+    }
+
+    /// The dependencies recorded as using the cached component.
+    pub fn references(&self, digest: &str) -> &[CacheReference] {
+        self.references
+            .get(digest)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Replaces the references recorded for the cached component.
+    pub fn set_references(&mut self, digest: &str, references: Vec<CacheReference>) {
+        if references.is_empty() {
+            self.references.remove(digest);
+        } else {
+            self.references.insert(digest.to_owned(), references);
+        }
+    }
+
+    /// The manifests of the apps with recorded references.
+    pub fn manifests(&self) -> BTreeSet<&Path> {
+        self.references
+            .values()
+            .flatten()
+            .map(|r| r.manifest.as_path())
+            .collect()
+    }
+
+    /// The digests of every cached component with recorded references.
+    pub fn digests(&self) -> impl Iterator<Item = &str> {
+        self.references.keys().map(String::as_str)
+    }
+}
+
+/// A component file in the cache.
+#[derive(Debug)]
+pub struct CachedComponent {
+    pub digest: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// The components in the cache, ordered by digest.
+pub async fn cached_components(cache: &Cache) -> Result<Vec<CachedComponent>> {
+    let mut components = Vec::new();
+    for path in cache_files(cache).await? {
+        let Some(digest) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(digest_from_file_name)
+        else {
+            continue;
+        };
+        let size = fs::metadata(&path).await?.len();
+        components.push(CachedComponent { digest, path, size });
+    }
+    components.sort_by(|a, b| a.digest.cmp(&b.digest));
+    Ok(components)
+}
+
+/// The files left behind by interrupted downloads.
+pub async fn partial_downloads(cache: &Cache) -> Result<Vec<PathBuf>> {
+    Ok(cache_files(cache)
+        .await?
+        .into_iter()
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.ends_with(PARTIAL_SUFFIX))
+        })
+        .collect())
+}
+
+async fn cache_files(cache: &Cache) -> Result<Vec<PathBuf>> {
+    let dir = cache.wasm_dir();
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(&dir)
+        .await
+        .with_context(|| format!("Failed to read cache directory {}", dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// The digest a cache file is stored under. The cache replaces the `:` in
+/// digests with `_` on platforms that do not allow it in file names.
+fn digest_from_file_name(name: &str) -> Option<String> {
+    let hex = name
+        .strip_prefix("sha256:")
+        .or_else(|| name.strip_prefix("sha256_"))?;
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())).then(|| format!("sha256:{hex}"))
+}
+
+/// Makes a manifest path absolute so references to it can be compared.
+pub fn absolute_manifest_path(manifest_file: &Path) -> PathBuf {
+    std::fs::canonicalize(manifest_file).unwrap_or_else(|_| manifest_file.to_owned())
+}
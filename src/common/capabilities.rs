@@ -0,0 +1,219 @@
+use spin_manifest::schema::v2::Component;
+use std::{collections::BTreeSet, fmt};
+use wit_parser::{Resolve, WorldId, WorldKey};
+
+use crate::language::rust::is_stdlib_interface;
+
+/// A host capability that a component needs configuration to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    OutboundNetwork,
+    KeyValue,
+    Sqlite,
+    Variables,
+    Filesystem,
+}
+
+impl Capability {
+    /// The component setting in the manifest that grants the capability.
+    pub fn manifest_field(&self) -> &'static str {
+        match self {
+            Self::OutboundNetwork => "allowed_outbound_hosts",
+            Self::KeyValue => "key_value_stores",
+            Self::Sqlite => "sqlite_databases",
+            Self::Variables => "variables",
+            Self::Filesystem => "files",
+        }
+    }
+
+    /// Whether the component grants the capability to its dependencies.
+    ///
+    /// Dependencies only get the component's configuration if it sets
+    /// `dependencies_inherit_configuration`, and then only what the component
+    /// itself has been granted.
+    pub fn is_granted_by(&self, component: &Component) -> bool {
+        if !component.dependencies_inherit_configuration {
+            return false;
+        }
+        match self {
+            Self::OutboundNetwork => !component.allowed_outbound_hosts.is_empty(),
+            Self::KeyValue => !component.key_value_stores.is_empty(),
+            Self::Sqlite => !component.sqlite_databases.is_empty(),
+            Self::Variables => !component.variables.is_empty(),
+            Self::Filesystem => !component.files.is_empty(),
+        }
+    }
+
+    /// The capability an imported interface uses, if any.
+    ///
+    /// Only interfaces that reach the capability count: the sockets
+    /// interfaces that merely describe sockets or addresses grant nothing
+    /// without the ones that create them, and filesystem types are no use
+    /// without preopened directories.
+    fn of_interface(package_namespace: &str, package_name: &str, interface: &str) -> Option<Self> {
+        match (package_namespace, package_name, interface) {
+            ("wasi", "http", "outgoing-handler")
+            | ("wasi", "sockets", "tcp-create-socket" | "udp-create-socket" | "ip-name-lookup") => {
+                Some(Self::OutboundNetwork)
+            }
+            ("wasi", "keyvalue", _) => Some(Self::KeyValue),
+            ("wasi", "config", _) => Some(Self::Variables),
+            ("wasi", "filesystem", "preopens") => Some(Self::Filesystem),
+            ("fermyon", "spin", interface) => match interface {
+                "http" | "outbound-http" | "redis" | "outbound-redis" | "postgres"
+                | "outbound-pg" | "mysql" | "outbound-mysql" | "mqtt" => {
+                    Some(Self::OutboundNetwork)
+                }
+                "key-value" => Some(Self::KeyValue),
+                "sqlite" => Some(Self::Sqlite),
+                "variables" | "config" => Some(Self::Variables),
+                _ => None,
+            },
+            ("spin", package, _) => match package {
+                "postgres" | "mysql" | "redis" | "mqtt" => Some(Self::OutboundNetwork),
+                "key-value" => Some(Self::KeyValue),
+                "sqlite" => Some(Self::Sqlite),
+                "variables" => Some(Self::Variables),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Self::OutboundNetwork => "outbound network (HTTP, sockets, databases)",
+            Self::KeyValue => "key-value stores",
+            Self::Sqlite => "SQLite databases",
+            Self::Variables => "variables",
+            Self::Filesystem => "filesystem",
+        };
+        f.write_str(description)
+    }
+}
+
+/// The host capabilities the world's imports use.
+///
+/// Imports that stdlib makes in every component, such as the filesystem
+/// preopens of the preview 1 adapter, are left out: they are there whether
+/// or not the dependency uses them.
+pub fn imported_capabilities(resolve: &Resolve, world_id: WorldId) -> BTreeSet<Capability> {
+    resolve.worlds[world_id]
+        .imports
+        .keys()
+        .filter_map(|key| {
+            let WorldKey::Interface(id) = key else {
+                return None;
+            };
+            let interface = &resolve.interfaces[*id];
+            let package = &resolve.packages[interface.package?].name;
+            let name = interface.name.as_deref()?;
+            if is_stdlib_interface(&format!("{}:{}/{name}", package.namespace, package.name)) {
+                return None;
+            }
+            Capability::of_interface(&package.namespace, &package.name, name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_importing(imports: &str) -> (Resolve, WorldId) {
+        let mut resolve = Resolve::default();
+        resolve
+            .push_str(
+                "wasi.wit",
+                "package wasi:sockets@0.2.3;
+                interface network {}
+                interface tcp-create-socket {}
+                interface ip-name-lookup {}
+                package wasi:filesystem@0.2.3 {
+                    interface preopens {}
+                }
+                package wasi:keyvalue@0.2.0-draft2 {
+                    interface store {}
+                }",
+            )
+            .unwrap();
+        let package = resolve
+            .push_str(
+                "root.wit",
+                &format!("package root:component;\nworld dependency {{\n{imports}\n}}"),
+            )
+            .unwrap();
+        let world = resolve.select_world(package, Some("dependency")).unwrap();
+        (resolve, world)
+    }
+
+    fn component(settings: &str) -> Component {
+        toml::from_str(&format!("source = \"dependency.wasm\"\n{settings}")).unwrap()
+    }
+
+    #[test]
+    fn interfaces_that_reach_a_capability_are_mapped() {
+        let of = Capability::of_interface;
+        assert_eq!(
+            of("wasi", "http", "outgoing-handler"),
+            Some(Capability::OutboundNetwork)
+        );
+        assert_eq!(
+            of("wasi", "sockets", "tcp-create-socket"),
+            Some(Capability::OutboundNetwork)
+        );
+        assert_eq!(
+            of("wasi", "sockets", "ip-name-lookup"),
+            Some(Capability::OutboundNetwork)
+        );
+        assert_eq!(of("wasi", "sockets", "network"), None);
+        assert_eq!(of("wasi", "sockets", "tcp"), None);
+        assert_eq!(
+            of("wasi", "filesystem", "preopens"),
+            Some(Capability::Filesystem)
+        );
+        assert_eq!(of("wasi", "filesystem", "types"), None);
+        assert_eq!(of("wasi", "keyvalue", "store"), Some(Capability::KeyValue));
+        assert_eq!(of("fermyon", "spin", "sqlite"), Some(Capability::Sqlite));
+        assert_eq!(
+            of("spin", "postgres", "postgres"),
+            Some(Capability::OutboundNetwork)
+        );
+        assert_eq!(of("wasi", "http", "types"), None);
+    }
+
+    #[test]
+    fn stdlib_imports_need_no_grant() {
+        let (resolve, world) = world_importing(
+            "import wasi:filesystem/preopens@0.2.3;\nimport wasi:sockets/network@0.2.3;",
+        );
+        assert!(imported_capabilities(&resolve, world).is_empty());
+
+        let (resolve, world) = world_importing(
+            "import wasi:sockets/tcp-create-socket@0.2.3;\nimport wasi:keyvalue/store@0.2.0-draft2;",
+        );
+        assert_eq!(
+            imported_capabilities(&resolve, world),
+            BTreeSet::from([Capability::OutboundNetwork, Capability::KeyValue])
+        );
+    }
+
+    #[test]
+    fn capabilities_are_granted_only_when_inherited() {
+        let not_inherited = component(r#"allowed_outbound_hosts = ["https://example.com"]"#);
+        assert!(!Capability::OutboundNetwork.is_granted_by(&not_inherited));
+
+        let inherited = component(
+            r#"dependencies_inherit_configuration = true
+            allowed_outbound_hosts = ["https://example.com"]
+            key_value_stores = ["default"]"#,
+        );
+        assert!(Capability::OutboundNetwork.is_granted_by(&inherited));
+        assert!(Capability::KeyValue.is_granted_by(&inherited));
+        assert!(!Capability::Sqlite.is_granted_by(&inherited));
+        assert!(!Capability::Variables.is_granted_by(&inherited));
+        assert!(!Capability::Filesystem.is_granted_by(&inherited));
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{anyhow, bail, ensure, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256, Sha512};
+use std::{fmt, str::FromStr};
+
+/// A hash algorithm that content digests can use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(Self::Sha256),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    /// The length of a digest in bytes.
+    fn byte_len(self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+/// A content digest, normalised to an algorithm and lowercase hex.
+///
+/// Parses from bare hex (the algorithm is inferred from the length), from
+/// `sha256:<hex>` and `sha512:<hex>`, and from Subresource Integrity form
+/// (`sha256-<base64>` and `sha512-<base64>`). Displays as `<algorithm>:<hex>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentDigest {
+    algorithm: Algorithm,
+    hex: String,
+}
+
+impl ContentDigest {
+    /// Computes the digest of the given content.
+    pub fn of(algorithm: Algorithm, bytes: &[u8]) -> Self {
+        let hex = match algorithm {
+            Algorithm::Sha256 => format!("{:x}", Sha256::digest(bytes)),
+            Algorithm::Sha512 => format!("{:x}", Sha512::digest(bytes)),
+        };
+        Self { algorithm, hex }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The digest as lowercase hex, without the algorithm.
+    pub fn hex(&self) -> &str {
+        &self.hex
+    }
+
+    fn from_hex(algorithm: Algorithm, hex: &str) -> Result<Self> {
+        ensure!(
+            hex.len() == algorithm.byte_len() * 2 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            "{} digest must be {} hexadecimal characters",
+            algorithm.name(),
+            algorithm.byte_len() * 2
+        );
+        Ok(Self {
+            algorithm,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+
+    fn from_base64(algorithm: Algorithm, encoded: &str) -> Result<Self> {
+        let bytes = STANDARD
+            .decode(encoded)
+            .map_err(|e| anyhow!("invalid base64 in {} digest: {e}", algorithm.name()))?;
+        ensure!(
+            bytes.len() == algorithm.byte_len(),
+            "{} digest must be {} bytes",
+            algorithm.name(),
+            algorithm.byte_len()
+        );
+        let hex = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        Ok(Self { algorithm, hex })
+    }
+}
+
+/// Computes content digests incrementally, for content that is streamed.
+#[derive(Default)]
+pub struct ContentHasher {
+    sha256: Sha256,
+    sha512: Sha512,
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.sha256.update(bytes);
+        self.sha512.update(bytes);
+    }
+
+    pub fn finalize(self) -> ContentDigests {
+        ContentDigests {
+            sha256: ContentDigest {
+                algorithm: Algorithm::Sha256,
+                hex: format!("{:x}", self.sha256.finalize()),
+            },
+            sha512: ContentDigest {
+                algorithm: Algorithm::Sha512,
+                hex: format!("{:x}", self.sha512.finalize()),
+            },
+        }
+    }
+}
+
+/// The digests of some content in every supported algorithm.
+pub struct ContentDigests {
+    pub sha256: ContentDigest,
+    pub sha512: ContentDigest,
+}
+
+impl ContentDigests {
+    /// Checks that the content has the expected digest.
+    pub fn verify(&self, expected: &ContentDigest) -> Result<()> {
+        let actual = match expected.algorithm {
+            Algorithm::Sha256 => &self.sha256,
+            Algorithm::Sha512 => &self.sha512,
+        };
+        ensure!(
+            actual == expected,
+            "invalid content digest; expected {expected}, downloaded {actual}"
+        );
+        Ok(())
+    }
+}
+
+impl FromStr for ContentDigest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((name, hex)) = s.split_once(':') {
+            let Some(algorithm) = Algorithm::from_name(name) else {
+                bail!("unsupported digest algorithm '{name}'; expected sha256 or sha512");
+            };
+            return Self::from_hex(algorithm, hex);
+        }
+
+        if let Some((name, encoded)) = s.split_once('-') {
+            if let Some(algorithm) = Algorithm::from_name(name) {
+                return Self::from_base64(algorithm, encoded);
+            }
+        }
+
+        match s.len() {
+            64 => Self::from_hex(Algorithm::Sha256, s),
+            128 => Self::from_hex(Algorithm::Sha512, s),
+            _ => bail!(
+                "digest must be hex, '<algorithm>:<hex>' or '<algorithm>-<base64>', using sha256 or sha512"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ContentDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The digests of "abc".
+    const SHA256_HEX: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const SHA256_BASE64: &str = "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+    const SHA512_HEX: &str = "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f";
+    const SHA512_BASE64: &str =
+        "3a81oZNherrMQXNJriBBMRLm+k6JqX6iCp7u5ktV05ohkpkqJ0/BqDa6PCOj/uu9RU1EI2Q86A4qmslPpUyknw==";
+
+    fn parse(digest: &str) -> ContentDigest {
+        digest.parse().unwrap()
+    }
+
+    #[test]
+    fn bare_hex_infers_the_algorithm_from_its_length() {
+        assert_eq!(
+            parse(SHA256_HEX),
+            ContentDigest::of(Algorithm::Sha256, b"abc")
+        );
+        assert_eq!(
+            parse(SHA512_HEX),
+            ContentDigest::of(Algorithm::Sha512, b"abc")
+        );
+        assert_eq!(
+            parse(SHA256_HEX).to_string(),
+            format!("sha256:{SHA256_HEX}")
+        );
+    }
+
+    #[test]
+    fn parses_every_form_to_the_same_digest() {
+        let sha256 = ContentDigest::of(Algorithm::Sha256, b"abc");
+        assert_eq!(parse(&format!("sha256:{SHA256_HEX}")), sha256);
+        assert_eq!(parse(&format!("sha256-{SHA256_BASE64}")), sha256);
+        assert_eq!(parse(&SHA256_HEX.to_ascii_uppercase()), sha256);
+        assert_eq!(
+            parse(&format!("sha256:{}", SHA256_HEX.to_ascii_uppercase())),
+            sha256
+        );
+
+        let sha512 = ContentDigest::of(Algorithm::Sha512, b"abc");
+        assert_eq!(parse(&format!("sha512:{SHA512_HEX}")), sha512);
+        assert_eq!(parse(&format!("sha512-{SHA512_BASE64}")), sha512);
+        assert_eq!(sha512.hex(), SHA512_HEX);
+    }
+
+    #[test]
+    fn rejects_wrong_lengths_and_unknown_algorithms() {
+        assert!("abc123".parse::<ContentDigest>().is_err());
+        assert!(format!("sha256:{SHA512_HEX}")
+            .parse::<ContentDigest>()
+            .is_err());
+        assert!(format!("sha512:{SHA256_HEX}")
+            .parse::<ContentDigest>()
+            .is_err());
+        assert!(format!("sha512-{SHA256_BASE64}")
+            .parse::<ContentDigest>()
+            .is_err());
+        assert!(format!("md5:{SHA256_HEX}")
+            .parse::<ContentDigest>()
+            .is_err());
+        assert!(format!("sha1-{SHA256_BASE64}")
+            .parse::<ContentDigest>()
+            .is_err());
+        assert!(SHA256_HEX
+            .replace('a', "g")
+            .parse::<ContentDigest>()
+            .is_err());
+    }
+
+    #[test]
+    fn verifies_against_the_expected_algorithm() {
+        let mut hasher = ContentHasher::default();
+        hasher.update(b"a");
+        hasher.update(b"bc");
+        let digests = hasher.finalize();
+        assert!(digests.verify(&parse(SHA256_HEX)).is_ok());
+        assert!(digests.verify(&parse(SHA512_HEX)).is_ok());
+        assert!(digests
+            .verify(&ContentDigest::of(Algorithm::Sha256, b"abd"))
+            .is_err());
+    }
+}
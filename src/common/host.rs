@@ -0,0 +1,95 @@
+use semver::VersionReq;
+use spin_manifest::schema::v2::ComponentDependencies;
+use spin_serde::DependencyName;
+use wit_parser::{Resolve, WorldId, WorldItem, WorldKey};
+
+/// WIT packages the Spin runtime provides to components and their
+/// dependencies, with the versions it supports. `*` also matches packages
+/// without a version.
+const SPIN_HOST_PACKAGES: &[(&str, &str)] = &[
+    ("wasi:cli", "^0.2.0"),
+    ("wasi:clocks", "^0.2.0"),
+    ("wasi:filesystem", "^0.2.0"),
+    ("wasi:io", "^0.2.0"),
+    ("wasi:random", "^0.2.0"),
+    ("wasi:sockets", "^0.2.0"),
+    ("wasi:http", "^0.2.0"),
+    ("wasi:keyvalue", "=0.2.0-draft2"),
+    ("wasi:config", "=0.2.0-draft-2024-09-27"),
+    ("fermyon:spin", "*"),
+];
+
+/// Namespaces whose packages are all provided by the Spin runtime.
+const SPIN_HOST_NAMESPACES: &[&str] = &["spin"];
+
+/// Lists the imports of the world that neither the Spin runtime nor the
+/// given dependencies provide.
+pub fn unsatisfied_imports(
+    resolve: &Resolve,
+    world_id: WorldId,
+    dependencies: &ComponentDependencies,
+) -> Vec<String> {
+    resolve.worlds[world_id]
+        .imports
+        .iter()
+        .filter_map(|(key, item)| match (key, item) {
+            (WorldKey::Interface(id), _) => {
+                let interface = &resolve.interfaces[*id];
+                let package = &resolve.packages[interface.package?].name;
+                let interface_name = interface.name.as_deref()?;
+                let satisfied = is_host_provided(package)
+                    || dependencies
+                        .inner
+                        .keys()
+                        .any(|name| provides_interface(name, package, interface_name));
+                (!satisfied).then(|| resolve.name_world_key(key))
+            }
+            (WorldKey::Name(name), WorldItem::Interface { .. } | WorldItem::Function(_)) => {
+                let satisfied = dependencies.inner.keys().any(
+                    |dep| matches!(dep, DependencyName::Plain(plain) if plain.to_string() == *name),
+                );
+                (!satisfied).then(|| name.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_host_provided(package: &wit_parser::PackageName) -> bool {
+    if SPIN_HOST_NAMESPACES.contains(&package.namespace.as_str()) {
+        return true;
+    }
+
+    let name = format!("{}:{}", package.namespace, package.name);
+    SPIN_HOST_PACKAGES
+        .iter()
+        .filter(|(host_package, _)| *host_package == name)
+        .any(|(_, requirement)| match &package.version {
+            _ if *requirement == "*" => true,
+            Some(version) => VersionReq::parse(requirement)
+                .map(|req| req.matches(version))
+                .unwrap_or(false),
+            None => false,
+        })
+}
+
+/// Whether a dependency with the given name provides the interface.
+fn provides_interface(
+    name: &DependencyName,
+    package: &wit_parser::PackageName,
+    interface: &str,
+) -> bool {
+    let DependencyName::Package(dep) = name else {
+        return false;
+    };
+    dep.package.namespace().to_string() == package.namespace
+        && dep.package.name().to_string() == package.name
+        && dep
+            .interface
+            .as_ref()
+            .is_none_or(|i| i.to_string() == interface)
+        && dep
+            .version
+            .as_ref()
+            .is_none_or(|v| Some(v) == package.version.as_ref())
+}
@@ -1,6 +1,6 @@
 use anyhow::Result;
 
-use dialoguer::{MultiSelect, Select};
+use dialoguer::{Confirm, Input, MultiSelect, Select};
 
 pub fn select_prompt(
     prompt: &str,
@@ -23,3 +23,20 @@ pub fn select_multiple_prompt<T: ToString>(
         .items(selection_list)
         .interact()?)
 }
+
+pub fn confirm_prompt(prompt: &str, default: bool) -> Result<bool> {
+    Ok(Confirm::new()
+        .with_prompt(prompt)
+        .default(default)
+        .interact()?)
+}
+
+pub fn input_prompt(
+    prompt: &str,
+    validate: impl Fn(&String) -> Result<(), String>,
+) -> Result<String> {
+    Ok(Input::new()
+        .with_prompt(prompt)
+        .validate_with(validate)
+        .interact_text()?)
+}
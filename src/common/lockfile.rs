@@ -0,0 +1,136 @@
+use anyhow::{Context, Result};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The lockfile `spin deps` keeps beside the application manifest.
+pub const LOCKFILE_NAME: &str = "spin-deps.lock";
+
+/// A dependency on a fetched component, locked to the content it resolved to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedDependency {
+    /// The component declaring the dependency.
+    pub component: String,
+    /// The dependency name.
+    pub dependency: String,
+    /// The registry package or URL the component was fetched from.
+    pub source: String,
+    /// The release the version requirement resolved to, for registry sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The digest the component is cached under.
+    pub digest: String,
+}
+
+/// The fetched components an application's dependencies use, so that they can
+/// be resolved again without the network and kept when the cache is pruned.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AppLockfile {
+    #[serde(default, rename = "dependency", skip_serializing_if = "Vec::is_empty")]
+    dependencies: Vec<LockedDependency>,
+}
+
+impl AppLockfile {
+    /// The lockfile of the application with the given manifest.
+    pub fn path(manifest_file: &Path) -> PathBuf {
+        manifest_file.with_file_name(LOCKFILE_NAME)
+    }
+
+    pub fn load(manifest_file: &Path) -> Result<Self> {
+        let path = Self::path(manifest_file);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let toml = std::fs::read_to_string(&path)?;
+        toml::from_str(&toml).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Writes the lockfile, unless there is nothing to lock and it does not
+    /// exist yet.
+    pub fn save(&self, manifest_file: &Path) -> Result<()> {
+        let path = Self::path(manifest_file);
+        if self.dependencies.is_empty() && !path.exists() {
+            return Ok(());
+        }
+        let toml = format!(
+            "# Written by `spin deps`. Do not edit.\n{}",
+            toml::to_string_pretty(self)?
+        );
+        std::fs::write(&path, toml).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Locks a dependency, replacing whatever it was locked to before.
+    pub fn lock(&mut self, locked: LockedDependency) {
+        self.remove(&locked.component, &locked.dependency);
+        self.dependencies.push(locked);
+    }
+
+    /// Forgets what a dependency was locked to.
+    pub fn remove(&mut self, component: &str, dependency: &str) {
+        self.dependencies
+            .retain(|d| d.component != component || d.dependency != dependency);
+    }
+
+    pub fn dependencies(&self) -> &[LockedDependency] {
+        &self.dependencies
+    }
+
+    /// The locked releases of a registry package that match the requirement,
+    /// newest first.
+    pub fn releases(&self, package: &str, requirement: &VersionReq) -> Vec<&LockedDependency> {
+        let mut releases = self
+            .dependencies
+            .iter()
+            .filter(|d| d.source == package)
+            .filter_map(|d| Some((Version::parse(d.version.as_deref()?).ok()?, d)))
+            .filter(|(version, _)| requirement.matches(version))
+            .collect::<Vec<_>>();
+        releases.sort_by(|(a, _), (b, _)| b.cmp(a));
+        releases.into_iter().map(|(_, d)| d).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked(dependency: &str, version: &str, digest: &str) -> LockedDependency {
+        LockedDependency {
+            component: "hello".to_owned(),
+            dependency: dependency.to_owned(),
+            source: "example:calculator".to_owned(),
+            version: Some(version.to_owned()),
+            digest: digest.to_owned(),
+        }
+    }
+
+    #[test]
+    fn locking_a_dependency_again_replaces_it() {
+        let mut lockfile = AppLockfile::default();
+        lockfile.lock(locked("example:calculator/add", "1.0.0", "sha256:a"));
+        lockfile.lock(locked("example:calculator/sub", "1.0.0", "sha256:a"));
+        lockfile.lock(locked("example:calculator/add", "1.1.0", "sha256:b"));
+        assert_eq!(lockfile.dependencies().len(), 2);
+
+        let toml = toml::to_string_pretty(&lockfile).unwrap();
+        let reread: AppLockfile = toml::from_str(&toml).unwrap();
+        assert_eq!(reread.dependencies(), lockfile.dependencies());
+    }
+
+    #[test]
+    fn releases_match_the_requirement_newest_first() {
+        let mut lockfile = AppLockfile::default();
+        lockfile.lock(locked("example:calculator/add", "1.0.0", "sha256:a"));
+        lockfile.lock(locked("example:calculator/sub", "1.2.0", "sha256:b"));
+        lockfile.lock(locked("example:calculator/mul", "2.0.0", "sha256:c"));
+
+        let requirement = VersionReq::parse("^1").unwrap();
+        let digests = lockfile
+            .releases("example:calculator", &requirement)
+            .into_iter()
+            .map(|d| d.digest.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(digests, ["sha256:b", "sha256:a"]);
+        assert!(lockfile.releases("example:other", &requirement).is_empty());
+    }
+}
@@ -31,7 +31,7 @@ pub async fn edit_component_deps_in_manifest(
                 version,
                 registry,
                 package,
-                export: _,
+                export,
             } => {
                 let mut pkg_table = toml_edit::InlineTable::default();
                 pkg_table.get_or_insert("version", version);
@@ -41,21 +41,30 @@ pub async fn edit_component_deps_in_manifest(
                 if let Some(pkg) = package {
                     pkg_table.get_or_insert("package", pkg);
                 }
+                if let Some(export) = export {
+                    pkg_table.get_or_insert("export", export);
+                }
                 toml_edit::Value::InlineTable(pkg_table)
             }
-            ComponentDependency::Local { path, export: _ } => {
+            ComponentDependency::Local { path, export } => {
                 let mut local_table = toml_edit::InlineTable::default();
                 local_table.get_or_insert("path", path.to_str().unwrap().to_owned());
+                if let Some(export) = export {
+                    local_table.get_or_insert("export", export);
+                }
                 toml_edit::Value::InlineTable(local_table)
             }
             ComponentDependency::HTTP {
                 url,
                 digest,
-                export: _,
+                export,
             } => {
                 let mut http_table = toml_edit::InlineTable::default();
                 http_table.get_or_insert("url", url);
                 http_table.get_or_insert("digest", digest);
+                if let Some(export) = export {
+                    http_table.get_or_insert("export", export);
+                }
                 toml_edit::Value::InlineTable(http_table)
             }
         };
@@ -67,3 +76,104 @@ pub async fn edit_component_deps_in_manifest(
 
     Ok(doc.to_string())
 }
+
+/// A short description of where a dependency comes from, for messages.
+pub fn describe_dependency(dependency: &ComponentDependency) -> String {
+    match dependency {
+        ComponentDependency::Version(version) => format!("version {version}"),
+        ComponentDependency::Package {
+            version, package, ..
+        } => match package {
+            Some(package) => format!("{package}@{version}"),
+            None => format!("version {version}"),
+        },
+        ComponentDependency::Local { path, .. } => path.display().to_string(),
+        ComponentDependency::HTTP { url, .. } => url.clone(),
+    }
+}
+
+/// The export a dependency takes from its component, if it names one.
+pub fn dependency_export(dependency: &ComponentDependency) -> Option<&str> {
+    match dependency {
+        ComponentDependency::Version(_) => None,
+        ComponentDependency::Package { export, .. }
+        | ComponentDependency::Local { export, .. }
+        | ComponentDependency::HTTP { export, .. } => export.as_deref(),
+    }
+}
+
+/// Whether two dependencies resolve to the same component, whichever export
+/// they take from it.
+pub fn same_source(a: &ComponentDependency, b: &ComponentDependency) -> bool {
+    match (a, b) {
+        (ComponentDependency::Version(a), ComponentDependency::Version(b)) => a == b,
+        (
+            ComponentDependency::Package {
+                version: a_version,
+                registry: a_registry,
+                package: a_package,
+                ..
+            },
+            ComponentDependency::Package {
+                version: b_version,
+                registry: b_registry,
+                package: b_package,
+                ..
+            },
+        ) => a_version == b_version && a_registry == b_registry && a_package == b_package,
+        (
+            ComponentDependency::Local { path: a_path, .. },
+            ComponentDependency::Local { path: b_path, .. },
+        ) => a_path == b_path,
+        (
+            ComponentDependency::HTTP {
+                url: a_url,
+                digest: a_digest,
+                ..
+            },
+            ComponentDependency::HTTP {
+                url: b_url,
+                digest: b_digest,
+                ..
+            },
+        ) => a_url == b_url && a_digest == b_digest,
+        _ => false,
+    }
+}
+
+/// The dependency, taking the named export of the dependency component
+/// instead of the export matching the dependency name.
+pub fn with_export(
+    dependency: ComponentDependency,
+    package: &str,
+    export: String,
+) -> ComponentDependency {
+    match dependency {
+        ComponentDependency::Version(version) => ComponentDependency::Package {
+            version,
+            registry: None,
+            package: Some(package.to_owned()),
+            export: Some(export),
+        },
+        ComponentDependency::Package {
+            version,
+            registry,
+            package,
+            export: _,
+        } => ComponentDependency::Package {
+            version,
+            registry,
+            package,
+            export: Some(export),
+        },
+        ComponentDependency::Local { path, export: _ } => ComponentDependency::Local {
+            path,
+            export: Some(export),
+        },
+        ComponentDependency::HTTP { url, digest, .. } => ComponentDependency::HTTP {
+            url,
+            digest,
+            export: Some(export),
+        },
+    }
+}
@@ -1,5 +1,13 @@
+pub mod auth;
+pub mod cache;
+pub mod capabilities;
 pub mod constants;
+pub mod digest;
+pub mod host;
 pub mod interact;
+pub mod lockfile;
 pub mod manifest;
+pub mod network;
 pub mod paths;
 pub mod wit;
+pub mod wit_deps;
@@ -0,0 +1,209 @@
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use reqwest::{Certificate, ClientBuilder, Proxy};
+use std::{path::PathBuf, str::FromStr};
+use url::Url;
+use wasm_pkg_client::Registry;
+
+/// A rule redirecting requests for one location to a mirror of it.
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    from: String,
+    to: String,
+}
+
+impl FromStr for Mirror {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Mirror '{s}' must be of the form FROM=TO"))?;
+        Ok(Self {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        })
+    }
+}
+
+/// The environment variable HTTP sources take their proxy from when `--proxy`
+/// is not given.
+const PROXY_ENV_VAR: &str = "SPIN_DEPS_PROXY";
+
+/// Network settings shared by every source that fetches over the network.
+#[derive(Args, Clone, Debug, Default)]
+pub struct NetworkOptions {
+    /// Proxy to send HTTP requests through. Defaults to SPIN_DEPS_PROXY. Registry sources
+    /// cannot use it, and take their proxy from HTTPS_PROXY instead.
+    #[clap(long, value_name = "URL")]
+    pub proxy: Option<Url>,
+    /// PEM file of extra CA certificates to trust. Can be repeated.
+    #[clap(long = "ca-cert", value_name = "PATH")]
+    pub ca_certs: Vec<PathBuf>,
+    /// Skip TLS certificate verification. Only use this for testing against local servers.
+    #[clap(long)]
+    pub insecure: bool,
+    /// Fetch from a mirror instead, as FROM=TO. URLs starting with FROM have it replaced by TO,
+    /// and a registry named FROM is replaced by the registry TO. Can be repeated.
+    #[clap(long = "mirror", value_name = "FROM=TO")]
+    pub mirrors: Vec<Mirror>,
+}
+
+impl NetworkOptions {
+    /// A client builder for HTTP requests with these settings applied.
+    pub fn http_client_builder(&self) -> Result<ClientBuilder> {
+        let mut builder = ClientBuilder::new();
+
+        if let Some(proxy) = self.http_proxy()? {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+
+        for path in &self.ca_certs {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA certificate {}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("Invalid CA certificate {}", path.display()))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder)
+    }
+
+    /// The proxy for HTTP sources: `--proxy`, or else the one in
+    /// SPIN_DEPS_PROXY. Registry sources only refuse the flag, so setting the
+    /// variable for HTTP sources does not stop them working.
+    fn http_proxy(&self) -> Result<Option<Url>> {
+        if let Some(proxy) = &self.proxy {
+            return Ok(Some(proxy.clone()));
+        }
+        match std::env::var(PROXY_ENV_VAR) {
+            Ok(proxy) if !proxy.is_empty() => Url::parse(&proxy)
+                .map(Some)
+                .with_context(|| format!("Invalid proxy URL in {PROXY_ENV_VAR}")),
+            _ => Ok(None),
+        }
+    }
+
+    /// The URL to fetch from, after applying mirror rules.
+    ///
+    /// A rule only matches whole path segments, so `https://example.com` does
+    /// not match `https://example.com.evil.example/`.
+    pub fn rewrite_url(&self, url: &Url) -> Result<Url> {
+        for mirror in &self.mirrors {
+            let Some(rest) = url.as_str().strip_prefix(&mirror.from) else {
+                continue;
+            };
+            let at_boundary =
+                rest.is_empty() || mirror.from.ends_with('/') || rest.starts_with(['/', '?', '#']);
+            if at_boundary {
+                let mirrored = format!("{}{rest}", mirror.to);
+                return Url::parse(&mirrored)
+                    .with_context(|| format!("Mirror rule produced invalid URL '{mirrored}'"));
+            }
+        }
+        Ok(url.clone())
+    }
+
+    /// The registry to fetch from, after applying mirror rules.
+    pub fn rewrite_registry(&self, registry: &Registry) -> Result<Registry> {
+        let name = registry.to_string();
+        match self.mirrors.iter().find(|m| m.from == name) {
+            Some(mirror) => mirror
+                .to
+                .parse()
+                .with_context(|| format!("Mirror rule produced invalid registry '{}'", mirror.to)),
+            None => Ok(registry.clone()),
+        }
+    }
+
+    /// Checks that registry clients can honour these settings.
+    ///
+    /// Registry clients configure their own HTTP stack and cannot be given a
+    /// proxy or trust settings, so rather than fetch without them, fail.
+    pub fn check_registry_support(&self) -> Result<()> {
+        let mut unsupported = Vec::new();
+        if self.proxy.is_some() {
+            unsupported.push("--proxy");
+        }
+        if !self.ca_certs.is_empty() {
+            unsupported.push("--ca-cert");
+        }
+        if self.insecure {
+            unsupported.push("--insecure");
+        }
+        if !unsupported.is_empty() {
+            bail!(
+                "{} cannot be used with registry sources. Registry clients use the system trust store \
+                and read their proxy from the HTTPS_PROXY environment variable.",
+                unsupported.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_mirror(mirror: &str) -> NetworkOptions {
+        NetworkOptions {
+            mirrors: vec![mirror.parse().unwrap()],
+            ..Default::default()
+        }
+    }
+
+    fn rewrite(options: &NetworkOptions, url: &str) -> String {
+        options
+            .rewrite_url(&Url::parse(url).unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn mirror_matches_whole_segments() {
+        let options = with_mirror("https://github.com=https://mirror.example/github");
+        assert_eq!(
+            rewrite(&options, "https://github.com/owner/repo/c.wasm"),
+            "https://mirror.example/github/owner/repo/c.wasm"
+        );
+        assert_eq!(
+            rewrite(&options, "https://github.com.evil.example/c.wasm"),
+            "https://github.com.evil.example/c.wasm"
+        );
+    }
+
+    #[test]
+    fn mirror_ending_in_slash_matches_prefix() {
+        let options = with_mirror("https://example.com/files/=https://mirror.example/");
+        assert_eq!(
+            rewrite(&options, "https://example.com/files/c.wasm"),
+            "https://mirror.example/c.wasm"
+        );
+    }
+
+    #[test]
+    fn registry_rejects_unsupported_settings() {
+        let options = NetworkOptions {
+            insecure: true,
+            ..Default::default()
+        };
+        assert!(options.check_registry_support().is_err());
+        assert!(NetworkOptions::default().check_registry_support().is_ok());
+    }
+
+    #[test]
+    fn registry_rejects_proxy_flag() {
+        let options = NetworkOptions {
+            proxy: Some(Url::parse("http://proxy.example:3128").unwrap()),
+            ..Default::default()
+        };
+        assert!(options.check_registry_support().is_err());
+    }
+}
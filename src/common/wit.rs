@@ -1,11 +1,50 @@
-use anyhow::{Context, Result};
-use wit_component::WitPrinter;
-use wit_parser::{PackageId, Resolve};
+use anyhow::{ensure, Context, Result};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+use wasi_preview1_component_adapter_provider::WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME;
+use wit_component::{ComponentEncoder, DecodedWasm, WitPrinter};
+use wit_parser::{
+    Case, Field, Handle, InterfaceId, LiveTypes, PackageId, PackageName, Record, Resolve, Result_,
+    Stability, Tuple, Type, TypeDef, TypeDefKind, TypeId, TypeOwner, Variant, WorldId, WorldItem,
+    WorldKey,
+};
+
+/// The kind of content a dependency source provided.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+    /// A component implementing its exports.
+    Component,
+    /// A WIT package that only describes the interfaces, with no implementation.
+    WitPackage,
+}
+
+/// The feature gates to treat as enabled when reading WIT.
+#[derive(Clone, Debug, Default)]
+pub struct FeatureGates {
+    /// Features whose `@unstable` items are enabled.
+    pub features: Vec<String>,
+    /// Whether every `@unstable` item is enabled.
+    pub all_features: bool,
+}
+
+impl FeatureGates {
+    /// Enables the features in the resolve. This must happen before WIT text
+    /// is parsed into it, as disabled items are dropped while parsing.
+    pub fn apply(&self, resolve: &mut Resolve) {
+        resolve.features.extend(self.features.iter().cloned());
+        resolve.all_features = self.all_features;
+    }
+}
 
 /// Converts a Resolve object to WIT content.
-pub fn resolve_to_wit(resolve: &Resolve, package_id: PackageId) -> Result<String> {
+///
+/// Stability attributes are always printed, so that `@unstable` items stay
+/// behind their feature gates in the generated WIT.
+pub fn resolve_to_wit(resolve: &Resolve, package_id: PackageId, emit_docs: bool) -> Result<String> {
     let mut printer = WitPrinter::default();
-    printer.emit_docs(false);
+    printer.emit_docs(emit_docs);
 
     let ids = resolve
         .packages
@@ -18,15 +57,154 @@ pub fn resolve_to_wit(resolve: &Resolve, package_id: PackageId) -> Result<String
     Ok(printer.output.to_string())
 }
 
-pub fn parse_component_bytes(bytes: Vec<u8>) -> Result<(Resolve, PackageId)> {
+/// Converts a single package of a Resolve object to WIT content, without the
+/// packages it depends on.
+pub fn package_to_wit(resolve: &Resolve, package_id: PackageId, emit_docs: bool) -> Result<String> {
+    let mut printer = WitPrinter::default();
+    printer.emit_docs(emit_docs);
+    printer.print(resolve, package_id, &[])?;
+    Ok(printer.output.to_string())
+}
+
+/// The directory a package is written to in a `deps/` tree.
+pub fn package_dir_name(name: &PackageName) -> String {
+    match &name.version {
+        Some(version) => format!("{}-{}-{version}", name.namespace, name.name),
+        None => format!("{}-{}", name.namespace, name.name),
+    }
+}
+
+/// Writes a package to `package.wit` in the directory, leaving the file alone
+/// if it is already up to date.
+pub fn write_package(
+    resolve: &Resolve,
+    package_id: PackageId,
+    dir: &Path,
+    emit_docs: bool,
+) -> Result<()> {
+    let wit = package_to_wit(resolve, package_id, emit_docs).with_context(|| {
+        format!(
+            "Failed to print WIT for {}",
+            resolve.packages[package_id].name
+        )
+    })?;
+    let path = dir.join("package.wit");
+    if std::fs::read_to_string(&path).is_ok_and(|existing| existing == wit) {
+        return Ok(());
+    }
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(&path, wit).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Writes every package of the resolve, except those excluded, to its own
+/// directory under `deps_dir`. Dependencies share the directories of the
+/// packages they have in common. Files that earlier versions wrote there
+/// are left alone, since the bindings generated then still read them.
+///
+/// Returns the package directories in topological order, which is the order
+/// they must be read back in.
+pub fn write_deps_tree(
+    resolve: &Resolve,
+    deps_dir: &Path,
+    exclude: &[PackageId],
+    emit_docs: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for package_id in resolve.topological_packages() {
+        if exclude.contains(&package_id) {
+            continue;
+        }
+        let dir = deps_dir.join(package_dir_name(&resolve.packages[package_id].name));
+        write_package(resolve, package_id, &dir, emit_docs)?;
+        dirs.push(dir);
+    }
+    Ok(dirs)
+}
+
+pub fn parse_component_bytes(bytes: Vec<u8>) -> Result<(Resolve, PackageId, SourceKind)> {
     wasmparser::validate(&bytes)
         .context("Provided component does not seem to be a valid component")?;
 
-    let decoded_wasm = wit_component::decode(&bytes)?;
-    let resolve = decoded_wasm.resolve().clone();
-    let main = decoded_wasm.package();
+    match wit_component::decode(&bytes)? {
+        DecodedWasm::Component(resolve, world) => {
+            let main = resolve.worlds[world].package.unwrap();
+            Ok((resolve, main, SourceKind::Component))
+        }
+        DecodedWasm::WitPackage(resolve, package) => Ok((resolve, package, SourceKind::WitPackage)),
+    }
+}
+
+/// Whether the bytes are a core Wasm module rather than a component.
+pub fn is_core_module(bytes: &[u8]) -> bool {
+    wasmparser::Parser::is_core_wasm(bytes)
+}
 
-    Ok((resolve, main))
+/// Turns a core module with an embedded `component-type` section into a
+/// component, adapting its WASI preview1 imports with the given adapter.
+pub fn componentize(module: &[u8], adapter: &[u8]) -> Result<Vec<u8>> {
+    ComponentEncoder::default()
+        .validate(true)
+        .module(module)
+        .context("Failed to read component type information embedded in the module")?
+        .adapter(WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME, adapter)
+        .context("Failed to load the WASI preview1 adapter")?
+        .encode()
+        .context("Failed to componentize the module")
+}
+
+/// Parses a WIT file or directory, returning the main package.
+pub fn parse_wit_path(path: &Path, gates: &FeatureGates) -> Result<(Resolve, PackageId)> {
+    let mut resolve = Resolve::default();
+    gates.apply(&mut resolve);
+    let (package, _) = resolve
+        .push_path(path)
+        .with_context(|| format!("Failed to parse WIT from {}", path.display()))?;
+
+    Ok((resolve, package))
+}
+
+/// Adds a `root:component` package to the resolve whose `root` world exports
+/// the interfaces of a WIT package, the same shape that decoding a component
+/// produces. With a world, only the interfaces that world exports are
+/// included; otherwise every interface of the package is.
+pub fn export_package_interfaces(
+    resolve: &mut Resolve,
+    package_id: PackageId,
+    world_id: Option<WorldId>,
+) -> Result<WorldId> {
+    let package = &resolve.packages[package_id];
+    let interfaces = match world_id {
+        Some(world_id) => resolve.worlds[world_id]
+            .exports
+            .values()
+            .filter_map(|item| match item {
+                WorldItem::Interface { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        None => package.interfaces.values().copied().collect(),
+    };
+    let exports = interfaces
+        .into_iter()
+        .filter_map(|id| resolve.id_of(id))
+        .map(|name| format!("    export {name};\n"))
+        .collect::<String>();
+    match world_id {
+        Some(world_id) => ensure!(
+            !exports.is_empty(),
+            "World {} does not export any named interfaces",
+            resolve.worlds[world_id].name
+        ),
+        None => ensure!(
+            !exports.is_empty(),
+            "WIT package {} does not define any interfaces",
+            package.name
+        ),
+    }
+
+    let wit = format!("package root:component;\n\nworld root {{\n{exports}}}\n");
+    let root = resolve.push_str("root.wit", &wit)?;
+    resolve.select_world(root, Some("root"))
 }
 
 /// Retrieves the exported interfaces from the resolved world.
@@ -37,9 +215,16 @@ pub fn get_exported_interfaces(
     resolve.worlds[world_id]
         .exports
         .iter()
-        .filter_map(|(_k, v)| match v {
-            wit_parser::WorldItem::Interface { id, .. } => {
+        .filter_map(|(k, v)| match v {
+            // Interfaces exported under a plain name are not part of a
+            // package; see `get_plain_exports`.
+            wit_parser::WorldItem::Interface { id, stability }
+                if !matches!(k, WorldKey::Name(_)) =>
+            {
                 let i = &resolve.interfaces[*id];
+                if !is_enabled(resolve, stability) || !is_enabled(resolve, &i.stability) {
+                    return None;
+                }
                 let pkg_id = i.package.unwrap();
                 let pkg = &resolve.packages[pkg_id];
                 Some((pkg.name.clone(), i.name.clone().unwrap_or_default()))
@@ -49,6 +234,390 @@ pub fn get_exported_interfaces(
         .collect()
 }
 
+/// Retrieves the plain names the resolved world exports under: functions
+/// exported directly, and interfaces exported under a name of their own
+/// rather than a package path.
+pub fn get_plain_exports(resolve: &Resolve, world_id: WorldId) -> Vec<String> {
+    resolve.worlds[world_id]
+        .exports
+        .iter()
+        .filter_map(|(key, item)| {
+            let WorldKey::Name(name) = key else {
+                return None;
+            };
+            match item {
+                WorldItem::Function(f) if is_enabled(resolve, &f.stability) => Some(name.clone()),
+                WorldItem::Interface { stability, .. } if is_enabled(resolve, stability) => {
+                    Some(name.clone())
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Whether an item with the given stability is enabled by the features of
+/// the resolve. Items gated behind a disabled feature are not offered, so they
+/// cannot be imported by accident.
+fn is_enabled(resolve: &Resolve, stability: &Stability) -> bool {
+    match stability {
+        Stability::Unstable { feature, .. } => {
+            resolve.all_features || resolve.features.contains(feature)
+        }
+        _ => true,
+    }
+}
+
+/// The enabled features that gate items in the resolve, which bindings
+/// generators need to be told about to generate those items.
+pub fn enabled_features(resolve: &Resolve) -> Vec<String> {
+    let interfaces = resolve.interfaces.iter().flat_map(|(_, i)| {
+        std::iter::once(&i.stability).chain(i.functions.values().map(|f| &f.stability))
+    });
+    let worlds = resolve.worlds.iter().flat_map(|(_, w)| {
+        std::iter::once(&w.stability).chain(w.imports.values().chain(w.exports.values()).map(
+            |item| match item {
+                WorldItem::Interface { stability, .. } => stability,
+                WorldItem::Function(f) => &f.stability,
+                WorldItem::Type(id) => &resolve.types[*id].stability,
+            },
+        ))
+    });
+    let types = resolve.types.iter().map(|(_, t)| &t.stability);
+
+    interfaces
+        .chain(worlds)
+        .chain(types)
+        .filter_map(|stability| match stability {
+            Stability::Unstable { feature, .. } if is_enabled(resolve, stability) => {
+                Some(feature.clone())
+            }
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The interfaces together with every interface they use types from,
+/// directly or indirectly. Each interface comes after the ones it uses.
+pub fn interface_closure(
+    resolve: &Resolve,
+    roots: impl IntoIterator<Item = InterfaceId>,
+) -> Vec<InterfaceId> {
+    fn visit(resolve: &Resolve, id: InterfaceId, closure: &mut Vec<InterfaceId>) {
+        if closure.contains(&id) {
+            return;
+        }
+        for dep in resolve.interface_direct_deps(id) {
+            visit(resolve, dep, closure);
+        }
+        closure.push(id);
+    }
+
+    let mut closure = Vec::new();
+    for id in roots {
+        visit(resolve, id, &mut closure);
+    }
+    closure
+}
+
+/// The functions of the named interfaces that pass futures or streams, keyed
+/// the way bindings generators name them (`ns:pkg/iface@version#func`). Such
+/// functions can only be bound as async functions.
+pub fn async_functions(resolve: &Resolve, interfaces: &[String]) -> Vec<String> {
+    resolve
+        .interfaces
+        .iter()
+        .filter_map(|(id, interface)| {
+            let name = resolve.id_of(id)?;
+            interfaces.contains(&name).then_some((name, interface))
+        })
+        .flat_map(|(name, interface)| {
+            interface
+                .functions
+                .values()
+                .filter(|f| {
+                    f.parameter_and_result_types()
+                        .any(|ty| carries_async_value(resolve, &ty))
+                })
+                .map(move |f| format!("{name}#{}", f.name))
+        })
+        .collect()
+}
+
+/// Whether values of the type hold a future or stream. Resource handles do
+/// not: a resource that produces streams does so through its own methods.
+fn carries_async_value(resolve: &Resolve, ty: &Type) -> bool {
+    let Type::Id(id) = ty else {
+        return false;
+    };
+    match &resolve.types[*id].kind {
+        TypeDefKind::Future(_) | TypeDefKind::Stream(_) => true,
+        TypeDefKind::Type(ty) | TypeDefKind::List(ty) | TypeDefKind::Option(ty) => {
+            carries_async_value(resolve, ty)
+        }
+        TypeDefKind::Record(record) => record
+            .fields
+            .iter()
+            .any(|field| carries_async_value(resolve, &field.ty)),
+        TypeDefKind::Tuple(tuple) => tuple
+            .types
+            .iter()
+            .any(|ty| carries_async_value(resolve, ty)),
+        TypeDefKind::Variant(variant) => variant
+            .cases
+            .iter()
+            .filter_map(|case| case.ty.as_ref())
+            .any(|ty| carries_async_value(resolve, ty)),
+        TypeDefKind::Result(result) => result
+            .ok
+            .iter()
+            .chain(result.err.iter())
+            .any(|ty| carries_async_value(resolve, ty)),
+        _ => false,
+    }
+}
+
+/// Finds what the world exports under a name: a plain name, or the qualified
+/// name of an interface.
+pub fn find_export<'a>(
+    resolve: &'a Resolve,
+    world_id: WorldId,
+    name: &str,
+) -> Option<(&'a WorldKey, &'a WorldItem)> {
+    resolve.worlds[world_id]
+        .exports
+        .iter()
+        .find(|(key, _)| match key {
+            WorldKey::Name(n) => n == name,
+            WorldKey::Interface(id) => resolve.id_of(*id).as_deref() == Some(name),
+        })
+}
+
+/// Adds a world to the world's package that imports selected exports of the
+/// world under plain names, along with only those of the world's imports that
+/// their types come from.
+///
+/// Each import is a pair of the name to import under and the name of the
+/// export: its plain name, or the qualified name of an exported interface.
+/// Qualified interfaces are imported through an unnamed copy, since a plain
+/// name can only be given to an interface defined in place.
+pub fn add_plain_import_world(
+    resolve: &mut Resolve,
+    world_id: WorldId,
+    imports: &[(String, String)],
+    name: &str,
+) -> Result<WorldId> {
+    let mut world = resolve.worlds[world_id].clone();
+    let package = world
+        .package
+        .context("World does not belong to a package")?;
+
+    let mut selected = Vec::new();
+    for (import_name, export_name) in imports {
+        let (key, item) = find_export(resolve, world_id, export_name)
+            .map(|(key, item)| (key.clone(), item.clone()))
+            .with_context(|| format!("The world does not export `{export_name}`"))?;
+        let item = match (&key, &item) {
+            (WorldKey::Interface(_), WorldItem::Interface { id, stability }) => {
+                WorldItem::Interface {
+                    id: anonymous_copy(resolve, *id, package),
+                    stability: stability.clone(),
+                }
+            }
+            (_, WorldItem::Function(f)) => {
+                let mut f = f.clone();
+                f.name = import_name.clone();
+                WorldItem::Function(f)
+            }
+            (_, item) => item.clone(),
+        };
+        selected.push((WorldKey::Name(import_name.clone()), item));
+    }
+
+    // The types the selected exports use, and the interfaces those types,
+    // and the types of interfaces exported as a whole, come from.
+    let mut live = LiveTypes::default();
+    let mut roots = Vec::new();
+    for (_, item) in &selected {
+        match item {
+            WorldItem::Function(f) => live.add_func(resolve, f),
+            WorldItem::Interface { id, .. } => roots.extend(resolve.interface_direct_deps(*id)),
+            WorldItem::Type(id) => live.add_type_id(resolve, *id),
+        }
+    }
+    let live = live.iter().collect::<HashSet<_>>();
+    roots.extend(live.iter().filter_map(|id| match resolve.types[*id].owner {
+        TypeOwner::Interface(interface) => Some(interface),
+        _ => None,
+    }));
+    let needed = interface_closure(resolve, roots);
+
+    world.name = name.to_owned();
+    world.imports.retain(|_, item| match item {
+        WorldItem::Interface { id, .. } => needed.contains(id),
+        WorldItem::Type(id) => live.contains(id),
+        WorldItem::Function(_) => false,
+    });
+    world.exports.clear();
+    // Interfaces the source world exports rather than imports are used too,
+    // and must be imported for the world to be complete.
+    for id in needed {
+        world
+            .imports
+            .entry(WorldKey::Interface(id))
+            .or_insert_with(|| WorldItem::Interface {
+                id,
+                stability: Stability::default(),
+            });
+    }
+    world.imports.extend(selected);
+
+    let id = resolve.worlds.alloc(world);
+    resolve.packages[package].worlds.insert(name.to_owned(), id);
+    Ok(id)
+}
+
+/// Adds a copy of an interface that has no name of its own to the package.
+///
+/// The copy defines the interface's types and functions itself, and uses the
+/// same interfaces the original does. Every type reachable from its functions
+/// and types that the original owns is replaced by the copy's, along with the
+/// anonymous types leading to it, so nothing in the copy depends on the
+/// original.
+fn anonymous_copy(
+    resolve: &mut Resolve,
+    interface: InterfaceId,
+    package: PackageId,
+) -> InterfaceId {
+    let original = resolve.interfaces[interface].clone();
+    let mut copy = original.clone();
+    copy.name = None;
+    copy.package = Some(package);
+    copy.types.clear();
+    copy.functions.clear();
+    let copy_id = resolve.interfaces.alloc(copy);
+
+    // The named types are all allocated before any is remapped, since they
+    // refer to each other.
+    let mut copier = TypeCopier {
+        resolve,
+        copied: HashMap::new(),
+    };
+    for (name, ty) in &original.types {
+        let mut def = copier.resolve.types[*ty].clone();
+        def.owner = TypeOwner::Interface(copy_id);
+        let def_id = copier.resolve.types.alloc(def);
+        copier.copied.insert(*ty, def_id);
+        copier.resolve.interfaces[copy_id]
+            .types
+            .insert(name.clone(), def_id);
+    }
+    for ty in original.types.values() {
+        let def_id = copier.copied[ty];
+        let kind = copier.resolve.types[def_id].kind.clone();
+        copier.resolve.types[def_id].kind = copier.kind(&kind);
+    }
+
+    for (name, f) in &original.functions {
+        let mut f = f.clone();
+        if let Some(resource) = f.kind.resource_mut() {
+            *resource = copier.id(*resource);
+        }
+        for (_, ty) in &mut f.params {
+            *ty = copier.ty(*ty);
+        }
+        f.result = f.result.map(|ty| copier.ty(ty));
+        copier.resolve.interfaces[copy_id]
+            .functions
+            .insert(name.clone(), f);
+    }
+
+    copy_id
+}
+
+/// Remaps the types an interface copy refers to, copying anonymous types
+/// that lead to a remapped type.
+struct TypeCopier<'a> {
+    resolve: &'a mut Resolve,
+    /// The copy of each type, or the type itself if it needs no copy.
+    copied: HashMap<TypeId, TypeId>,
+}
+
+impl TypeCopier<'_> {
+    fn ty(&mut self, ty: Type) -> Type {
+        match ty {
+            Type::Id(id) => Type::Id(self.id(id)),
+            ty => ty,
+        }
+    }
+
+    fn id(&mut self, id: TypeId) -> TypeId {
+        if let Some(copy) = self.copied.get(&id) {
+            return *copy;
+        }
+        let def = self.resolve.types[id].clone();
+        // Named types of other interfaces are used as they are.
+        if def.name.is_some() || def.owner != TypeOwner::None {
+            return id;
+        }
+        let kind = self.kind(&def.kind);
+        let copy = if kind == def.kind {
+            id
+        } else {
+            self.resolve.types.alloc(TypeDef { kind, ..def })
+        };
+        self.copied.insert(id, copy);
+        copy
+    }
+
+    fn kind(&mut self, kind: &TypeDefKind) -> TypeDefKind {
+        match kind {
+            TypeDefKind::Record(record) => TypeDefKind::Record(Record {
+                fields: record
+                    .fields
+                    .iter()
+                    .map(|field| Field {
+                        ty: self.ty(field.ty),
+                        ..field.clone()
+                    })
+                    .collect(),
+            }),
+            TypeDefKind::Handle(Handle::Own(id)) => TypeDefKind::Handle(Handle::Own(self.id(*id))),
+            TypeDefKind::Handle(Handle::Borrow(id)) => {
+                TypeDefKind::Handle(Handle::Borrow(self.id(*id)))
+            }
+            TypeDefKind::Tuple(tuple) => TypeDefKind::Tuple(Tuple {
+                types: tuple.types.iter().map(|ty| self.ty(*ty)).collect(),
+            }),
+            TypeDefKind::Variant(variant) => TypeDefKind::Variant(Variant {
+                cases: variant
+                    .cases
+                    .iter()
+                    .map(|case| Case {
+                        ty: case.ty.map(|ty| self.ty(ty)),
+                        ..case.clone()
+                    })
+                    .collect(),
+            }),
+            TypeDefKind::Option(ty) => TypeDefKind::Option(self.ty(*ty)),
+            TypeDefKind::Result(result) => TypeDefKind::Result(Result_ {
+                ok: result.ok.map(|ty| self.ty(ty)),
+                err: result.err.map(|ty| self.ty(ty)),
+            }),
+            TypeDefKind::List(ty) => TypeDefKind::List(self.ty(*ty)),
+            TypeDefKind::Future(ty) => TypeDefKind::Future(ty.map(|ty| self.ty(ty))),
+            TypeDefKind::Stream(ty) => TypeDefKind::Stream(ty.map(|ty| self.ty(ty))),
+            TypeDefKind::Type(ty) => TypeDefKind::Type(self.ty(*ty)),
+            TypeDefKind::Resource
+            | TypeDefKind::Flags(_)
+            | TypeDefKind::Enum(_)
+            | TypeDefKind::Unknown => kind.clone(),
+        }
+    }
+}
+
 // pub fn merge_dependecy_package(
 //     base_resolve_file: Option<&PathBuf>,
 //     dependency_resolve: &Resolve,
@@ -0,0 +1,265 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+use wit_parser::{PackageId, Resolve};
+
+use super::wit::{package_dir_name, package_to_wit};
+
+const PROVENANCE_FILE: &str = "provenance.json";
+
+/// A dependency that brought a package into the deps tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageOrigin {
+    /// The component the dependency was added to.
+    pub component: String,
+    /// The name the dependency is recorded under.
+    pub dependency: String,
+    /// Where the dependency came from.
+    pub source: String,
+}
+
+impl std::fmt::Display for PackageOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` from {} (component '{}')",
+            self.dependency, self.source, self.component
+        )
+    }
+}
+
+/// What a dependency brought into the deps tree.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DependencyRecord {
+    /// Where the dependency came from.
+    pub source: String,
+    /// The packages it uses.
+    pub packages: BTreeSet<String>,
+    /// The bindings generated for it, relative to the application directory.
+    #[serde(default)]
+    pub bindings: Vec<PathBuf>,
+}
+
+/// The dependencies that brought packages into a deps tree, by component and
+/// dependency name. Adding a dependency again, or replacing it, replaces its
+/// record, so packages it no longer uses stop counting against it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DepsTree {
+    #[serde(skip)]
+    dir: PathBuf,
+    #[serde(default)]
+    dependencies: BTreeMap<String, BTreeMap<String, DependencyRecord>>,
+}
+
+impl DepsTree {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(PROVENANCE_FILE);
+        let mut tree = if path.is_file() {
+            let json = std::fs::read(&path)?;
+            serde_json::from_slice::<Self>(&json)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            Self::default()
+        };
+        tree.dir = dir.to_owned();
+        Ok(tree)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(PROVENANCE_FILE);
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Checks that the packages of the resolve can join the tree without
+    /// clashing with packages other dependencies brought in.
+    ///
+    /// A package clashes if the tree already holds different WIT under the
+    /// same name that some other dependency uses, or if another dependency of
+    /// the same component uses a different version of it. The dependencies of
+    /// the component named in `replacing` are being added again or replaced,
+    /// so what they use does not count.
+    pub fn check_conflicts(
+        &self,
+        resolve: &Resolve,
+        exclude: &[PackageId],
+        component: &str,
+        replacing: &[String],
+        source: &str,
+    ) -> Result<()> {
+        let others = self
+            .origins()
+            .filter(|(origin, _)| {
+                origin.component != component || !replacing.contains(&origin.dependency)
+            })
+            .collect::<Vec<_>>();
+        let mut conflicts = String::new();
+
+        for (id, package) in resolve.packages.iter() {
+            if exclude.contains(&id) {
+                continue;
+            }
+            let name = package.name.to_string();
+
+            // WIT that only the dependencies being replaced use can be
+            // overwritten.
+            let users = others
+                .iter()
+                .filter(|(_, record)| record.packages.contains(&name))
+                .map(|(origin, _)| origin)
+                .collect::<Vec<_>>();
+            let recorded = self
+                .dependencies
+                .values()
+                .flat_map(|records| records.values())
+                .any(|record| record.packages.contains(&name));
+            let replaceable = recorded && users.is_empty();
+            let path = self
+                .dir
+                .join(package_dir_name(&package.name))
+                .join("package.wit");
+            if let Ok(existing) = std::fs::read_to_string(&path) {
+                let new = package_to_wit(resolve, id, false)?;
+                if !replaceable && without_docs(&existing) != without_docs(&new) {
+                    writeln!(
+                        conflicts,
+                        "  {name} differs from the copy added by {}",
+                        describe_origins(&users)
+                    )?;
+                }
+            }
+
+            let unversioned = format!("{}:{}", package.name.namespace, package.name.name);
+            let version = package.name.version.as_ref().map(|v| v.to_string());
+            for (origin, record) in others.iter().filter(|(o, _)| o.component == component) {
+                for other in &record.packages {
+                    let (other_unversioned, other_version) = match other.split_once('@') {
+                        Some((name, version)) => (name, Some(version.to_owned())),
+                        None => (other.as_str(), None),
+                    };
+                    if other_unversioned == unversioned && other_version != version {
+                        writeln!(
+                            conflicts,
+                            "  {unversioned} is needed at {} by {source}, but {origin} uses {}",
+                            version_text(version.as_deref()),
+                            version_text(other_version.as_deref()),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if !conflicts.is_empty() {
+            bail!(
+                "The dependency's WIT conflicts with WIT already in {}:\n{conflicts}",
+                self.dir.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Records that a dependency of the component uses the packages of the
+    /// resolve, replacing anything recorded for it before.
+    pub fn record(
+        &mut self,
+        resolve: &Resolve,
+        exclude: &[PackageId],
+        component: &str,
+        dependency: &str,
+        source: &str,
+    ) {
+        let packages = resolve
+            .packages
+            .iter()
+            .filter(|(id, _)| !exclude.contains(id))
+            .map(|(_, package)| package.name.to_string())
+            .collect();
+        self.dependencies
+            .entry(component.to_owned())
+            .or_default()
+            .insert(
+                dependency.to_owned(),
+                DependencyRecord {
+                    source: source.to_owned(),
+                    packages,
+                    bindings: vec![],
+                },
+            );
+    }
+
+    /// Records where the bindings for a dependency were generated.
+    pub fn record_bindings(&mut self, component: &str, dependency: &str, bindings: &Path) {
+        if let Some(record) = self
+            .dependencies
+            .get_mut(component)
+            .and_then(|records| records.get_mut(dependency))
+        {
+            record.bindings = vec![bindings.to_owned()];
+        }
+    }
+
+    /// Forgets a dependency that was replaced, returning the bindings that
+    /// no other dependency shares and so can be removed.
+    pub fn remove(&mut self, component: &str, dependency: &str) -> Vec<PathBuf> {
+        let removed = self
+            .dependencies
+            .get_mut(component)
+            .and_then(|records| records.remove(dependency))
+            .map(|record| record.bindings)
+            .unwrap_or_default();
+        removed
+            .into_iter()
+            .filter(|path| {
+                !self
+                    .dependencies
+                    .values()
+                    .flat_map(|records| records.values())
+                    .any(|record| record.bindings.contains(path))
+            })
+            .collect()
+    }
+
+    fn origins(&self) -> impl Iterator<Item = (PackageOrigin, &DependencyRecord)> {
+        self.dependencies.iter().flat_map(|(component, records)| {
+            records.iter().map(|(dependency, record)| {
+                let origin = PackageOrigin {
+                    component: component.clone(),
+                    dependency: dependency.clone(),
+                    source: record.source.clone(),
+                };
+                (origin, record)
+            })
+        })
+    }
+}
+
+fn describe_origins(origins: &[&PackageOrigin]) -> String {
+    if origins.is_empty() {
+        return "an earlier dependency".to_owned();
+    }
+    origins
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn version_text(version: Option<&str>) -> String {
+    version
+        .map(|v| format!("version {v}"))
+        .unwrap_or_else(|| "no version".to_owned())
+}
+
+/// WIT text without doc comments, so that copies written with and without
+/// docs compare equal.
+fn without_docs(wit: &str) -> String {
+    wit.lines()
+        .filter(|line| !line.trim_start().starts_with("///"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,9 +1,14 @@
+use semver::Version;
+use serde::Deserialize;
+use std::path::Path;
+
 pub fn identifier_safe(package_name: &wit_parser::PackageName) -> String {
     format!(
         "{ns}_{name}",
         ns = package_name.namespace,
         name = package_name.name
     )
+    .replace('-', "_")
 }
 
 // TODO: moar
@@ -36,6 +41,161 @@ pub fn is_stdlib_known(interface_name: &str) -> bool {
     STDLIB_INTERFACES.contains(&interface_name)
 }
 
+/// Whether stdlib uses the interface at some version, given its name without
+/// one. Toolchains and the preview 1 adapter move to newer WASI patch
+/// releases independently of the versions bound here.
+pub fn is_stdlib_interface(unversioned_name: &str) -> bool {
+    STDLIB_INTERFACES
+        .iter()
+        .any(|i| i.split_once('@').map_or(*i, |(name, _)| name) == unversioned_name)
+}
+
 pub fn is_sdk_known(interface_name: &str) -> bool {
     SPIN_SDK_INTERFACES.contains(&interface_name) || interface_name.starts_with("spin:")
 }
+
+/// wit-bindgen releases from this version on accept `features:` in
+/// `generate!`.
+pub const FEATURES_SINCE: Version = Version::new(0, 41, 0);
+
+/// wit-bindgen releases from this version on accept `async:` as a list of
+/// `import:` directives in `generate!`. Earlier ones take a different shape,
+/// or know nothing of `stream` and `future`.
+pub const ASYNC_IMPORTS_SINCE: Version = Version::new(0, 51, 0);
+
+/// The `generate!` options the wit-bindgen that the component's Spin SDK
+/// re-exports accepts, since the generated bindings go through it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MacroSupport {
+    pub features: bool,
+    pub async_imports: bool,
+}
+
+impl MacroSupport {
+    /// The options a wit-bindgen version accepts. An unknown version is
+    /// taken to be recent, as new apps start on the latest SDK.
+    pub fn of(wit_bindgen: Option<&Version>) -> Self {
+        Self {
+            features: wit_bindgen.is_none_or(|v| *v >= FEATURES_SINCE),
+            async_imports: wit_bindgen.is_none_or(|v| *v >= ASYNC_IMPORTS_SINCE),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/// The version of wit-bindgen the component's Spin SDK re-exports, from the
+/// Cargo.lock of the crate or the workspace it belongs to. `None` if the
+/// crate has not been built yet or does not use the SDK.
+pub fn sdk_wit_bindgen_version(crate_dir: &Path) -> Option<Version> {
+    let lockfile = crate_dir
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.is_file())?;
+    let lockfile: Lockfile = toml::from_str(&std::fs::read_to_string(lockfile).ok()?).ok()?;
+
+    // A dependency is listed by name alone unless the lockfile holds several
+    // versions of it.
+    let sdk = lockfile.package.iter().find(|p| p.name == "spin-sdk")?;
+    let listed = sdk.dependencies.iter().find_map(|dependency| {
+        let mut parts = dependency.split(' ');
+        (parts.next() == Some("wit-bindgen")).then(|| parts.next())
+    })?;
+    let version = match listed {
+        Some(version) => version,
+        None => lockfile
+            .package
+            .iter()
+            .find(|p| p.name == "wit-bindgen")?
+            .version
+            .as_str(),
+    };
+    Version::parse(version).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A crate directory of its own for a test, holding the lockfile.
+    fn crate_with_lockfile(test: &str, lockfile: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("spin-deps-test-{test}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Cargo.lock"), lockfile).unwrap();
+        dir
+    }
+
+    #[test]
+    fn example_app_sdk_takes_neither_option() {
+        let example = Path::new(env!("CARGO_MANIFEST_DIR")).join("example");
+        let version = sdk_wit_bindgen_version(&example);
+        assert_eq!(version, Some(Version::new(0, 16, 0)));
+        assert_eq!(
+            MacroSupport::of(version.as_ref()),
+            MacroSupport {
+                features: false,
+                async_imports: false,
+            }
+        );
+    }
+
+    #[test]
+    fn sdk_version_is_picked_among_several() {
+        let dir = crate_with_lockfile(
+            "several-wit-bindgen",
+            r#"
+[[package]]
+name = "spin-sdk"
+version = "5.0.0"
+dependencies = ["anyhow", "wit-bindgen 0.51.0"]
+
+[[package]]
+name = "wit-bindgen"
+version = "0.41.0"
+
+[[package]]
+name = "wit-bindgen"
+version = "0.51.0"
+"#,
+        );
+        let version = sdk_wit_bindgen_version(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(version, Some(Version::new(0, 51, 0)));
+        assert_eq!(
+            MacroSupport::of(version.as_ref()),
+            MacroSupport {
+                features: true,
+                async_imports: true,
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_sdk_is_taken_to_be_recent() {
+        let dir = crate_with_lockfile(
+            "no-sdk",
+            r#"
+[[package]]
+name = "anyhow"
+version = "1.0.0"
+"#,
+        );
+        let version = sdk_wit_bindgen_version(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(version, None);
+        assert!(MacroSupport::of(None).async_imports);
+    }
+}
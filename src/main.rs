@@ -4,7 +4,7 @@ use clap::{Parser, Subcommand};
 mod commands;
 mod common;
 mod language;
-use commands::{add::AddCommand, publish::PublishCommand};
+use commands::{add::AddCommand, cache::CacheCommand, publish::PublishCommand};
 
 /// Main CLI structure for command-line argument parsing.
 #[derive(Parser)]
@@ -22,6 +22,9 @@ enum Commands {
 
     /// Publish dependency to a compatible registry
     Publish(PublishCommand),
+
+    /// Inspect and manage cached dependency components
+    Cache(CacheCommand),
 }
 
 #[tokio::main]
@@ -31,6 +34,7 @@ async fn main() -> Result<()> {
     match app.command {
         Commands::Add(cmd) => cmd.run().await?,
         Commands::Publish(cmd) => cmd.run().await?,
+        Commands::Cache(cmd) => cmd.run().await?,
     }
 
     Ok(())